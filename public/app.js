@@ -7,14 +7,27 @@ document.addEventListener('DOMContentLoaded', function() {
 let currentLeagues = [];
 let currentSkillGems = null;
 
+// EV engine compiled to WebAssembly (see wasm/). When it is available the gem list
+// is downloaded once per league and results are recomputed locally on every change;
+// otherwise each calculation is a call to /api/v1/calculate.
+let wasmEngine = null;
+let calculator = null;
+let calculatorLeague = null;
+// The server's defaults of the options the form doesn't set, like its outlier policy,
+// so local results match /api/v1/calculate
+let engineDefaults = null;
+
 async function initializeApp() {
     try {
-        // Load leagues on startup
-        await fetchAndPopulateLeagues();
+        // Load leagues and the local EV engine on startup
+        await Promise.all([fetchAndPopulateLeagues(), loadWasmEngine()]);
 
         // Set up event listeners
         setupEventListeners();
 
+        // Permalinks of saved calculations open on this page
+        await loadSavedCalculation();
+
         console.log('Application initialized successfully');
     } catch (error) {
         console.error('Failed to initialize application:', error);
@@ -29,11 +42,19 @@ function setupEventListeners() {
         calculateBtn.addEventListener('click', performCalculation);
     }
 
+    // Share button
+    const shareBtn = document.getElementById('share');
+    if (shareBtn) {
+        shareBtn.addEventListener('click', shareCalculation);
+    }
+
     // League selector
     const leagueSelect = document.getElementById('league-select');
     if (leagueSelect) {
         leagueSelect.addEventListener('change', function() {
             localStorage.setItem('selectedLeague', this.value);
+            calculator = null;
+            calculatorLeague = null;
             console.log('League changed to:', this.value);
         });
     }
@@ -43,14 +64,39 @@ function setupEventListeners() {
     formInputs.forEach(input => {
         input.addEventListener('change', function() {
             console.log('Form input changed:', this.name, this.value);
+            recalculateLocally();
         });
+        // Typing in the threshold or exclusions reprices straight away when local
+        input.addEventListener('input', recalculateLocally);
     });
 }
 
+async function loadWasmEngine() {
+    try {
+        const module = await import('/wasm/poe_gem_calculator_wasm.js');
+        await module.default();
+
+        const response = await fetch('/api/v1/calculate/defaults');
+        if (!response.ok) {
+            throw new Error(`HTTP error! status: ${response.status}`);
+        }
+        engineDefaults = await response.json();
+        wasmEngine = module;
+
+        const localOptions = document.getElementById('local-options');
+        if (localOptions) {
+            localOptions.hidden = false;
+        }
+        console.log('Local EV engine loaded');
+    } catch (error) {
+        console.log('Local EV engine not available, calculating on the server:', error);
+    }
+}
+
 async function fetchAndPopulateLeagues() {
     try {
         console.log('Fetching leagues from API...');
-        const response = await fetch('/api/leagues');
+        const response = await fetch('/api/v1/leagues');
 
         if (!response.ok) {
             throw new Error(`HTTP error! status: ${response.status}`);
@@ -108,6 +154,9 @@ function populateLeagueSelector(leagues) {
         if (league.hardcore) {
             option.textContent += ' (HC)';
         }
+        if (league.private) {
+            option.textContent += ' (Private)';
+        }
         if (!league.indexed) {
             option.textContent += ' (Old)';
         }
@@ -138,21 +187,9 @@ async function performCalculation() {
         const formData = getFormData();
         console.log('Starting calculation with parameters:', formData);
 
-        // Build query parameters
-        const params = new URLSearchParams();
-        if (formData.league) params.append('league', formData.league);
-        if (formData.ignoreAfterChaos !== null) params.append('ignore_after_chaos', formData.ignoreAfterChaos);
-        if (formData.gemLevel !== null) params.append('gem_level', formData.gemLevel);
-        if (formData.gemQuality !== null) params.append('gem_quality', formData.gemQuality);
-
-        // Make API call
-        const response = await fetch(`/api/calculate?${params}`);
-
-        if (!response.ok) {
-            throw new Error(`HTTP error! status: ${response.status}`);
-        }
-
-        const result = await response.json();
+        const result = wasmEngine
+            ? await calculateLocally(formData)
+            : await calculateOnServer(formData);
         console.log('Calculation result:', result);
 
         // Update UI with results
@@ -167,17 +204,182 @@ async function performCalculation() {
     }
 }
 
+function toQueryParams(formData) {
+    const params = new URLSearchParams();
+    if (formData.league) params.append('league', formData.league);
+    if (formData.ignoreAfterChaos !== null) params.append('ignore_after_chaos', formData.ignoreAfterChaos);
+    if (formData.gemLevel !== null) params.append('gem_level', formData.gemLevel);
+    if (formData.gemQuality !== null) params.append('gem_quality', formData.gemQuality);
+    if (formData.offerSize !== null) params.append('offer_size', formData.offerSize);
+    if (formData.excluded.length > 0) params.append('exclude', formData.excluded.join(','));
+    return params;
+}
+
+async function calculateOnServer(formData) {
+    // Make API call
+    const response = await fetch(`/api/v1/calculate?${toQueryParams(formData)}`);
+
+    if (!response.ok) {
+        throw new Error(`HTTP error! status: ${response.status}`);
+    }
+
+    return response.json();
+}
+
+async function calculateLocally(formData) {
+    // Download the league's gem list once, then price it as often as needed
+    if (!calculator || calculatorLeague !== formData.league) {
+        const params = new URLSearchParams({ league: formData.league });
+        const response = await fetch(`/api/v1/skill-gems?${params}`);
+
+        if (!response.ok) {
+            throw new Error(`HTTP error! status: ${response.status}`);
+        }
+
+        calculator = new wasmEngine.Calculator(await response.text());
+        calculator.link_to(formData.league, engineDefaults.trade_url, engineDefaults.poe_ninja_url);
+        calculatorLeague = formData.league;
+    }
+
+    return calculator.calculate(toEngineOptions(formData));
+}
+
+function toEngineOptions(formData) {
+    return {
+        gem_level: formData.gemLevel,
+        gem_quality: formData.gemQuality,
+        ignore_after_chaos: formData.ignoreAfterChaos,
+        offer_size: formData.offerSize,
+        excluded: formData.excluded,
+        outliers: engineDefaults.outliers
+    };
+}
+
+// Reprices the already downloaded gem list without a server round trip
+function recalculateLocally() {
+    const formData = getFormData();
+    if (!calculator || calculatorLeague !== formData.league) return;
+
+    try {
+        displayCalculationResults(calculator.calculate(toEngineOptions(formData)));
+    } catch (error) {
+        console.error('Local recalculation failed:', error);
+    }
+}
+
+// Saves the calculation on the server with its prices and shows its permalink
+async function shareCalculation() {
+    const shareBtn = document.getElementById('share');
+    try {
+        if (shareBtn) shareBtn.disabled = true;
+
+        const response = await fetch(`/api/v1/results?${toQueryParams(getFormData())}`, { method: 'POST' });
+        if (!response.ok) {
+            throw new Error(`HTTP error! status: ${response.status}`);
+        }
+
+        const saved = await response.json();
+        const permalink = new URL(saved.permalink, window.location.origin).href;
+        displayCalculationResults(saved.result);
+        history.replaceState(null, '', saved.permalink);
+
+        let copied = false;
+        try {
+            await navigator.clipboard.writeText(permalink);
+            copied = true;
+        } catch (error) {
+            console.log('Could not copy the permalink:', error);
+        }
+        showShareStatus(copied ? `Link copied: ${permalink}` : `Share this link: ${permalink}`);
+    } catch (error) {
+        console.error('Saving the calculation failed:', error);
+        showShareStatus('Saving the calculation failed. Please try again.');
+    } finally {
+        if (shareBtn) shareBtn.disabled = false;
+    }
+}
+
+// Shows a saved calculation when the page is opened from its permalink
+async function loadSavedCalculation() {
+    const match = window.location.pathname.match(/^\/results\/([0-9a-f-]+)\/?$/i);
+    if (!match) return;
+
+    try {
+        const response = await fetch(`/api/v1/results/${match[1]}`);
+        if (!response.ok) {
+            throw new Error(`HTTP error! status: ${response.status}`);
+        }
+
+        const saved = await response.json();
+        fillForm(saved.inputs);
+        displayCalculationResults(saved.result);
+
+        const pricedAt = new Date(saved.prices_updated_at).toLocaleString();
+        showShareStatus(`Saved calculation for ${saved.league} with prices from ${pricedAt}. Calculate again for current prices.`);
+    } catch (error) {
+        console.error('Loading the saved calculation failed:', error);
+        showShareStatus('This saved calculation does not exist or has expired.');
+    }
+}
+
+function fillForm(inputs) {
+    const leagueSelect = document.getElementById('league-select');
+    if (leagueSelect && inputs.league) {
+        if (!leagueSelect.querySelector(`option[value="${CSS.escape(inputs.league)}"]`)) {
+            const option = document.createElement('option');
+            option.value = inputs.league;
+            option.textContent = inputs.league;
+            leagueSelect.appendChild(option);
+        }
+        leagueSelect.value = inputs.league;
+    }
+
+    const ignoreAfterChaos = document.getElementById('ignoreAfterChaos');
+    if (ignoreAfterChaos && inputs.ignore_after_chaos !== null) {
+        ignoreAfterChaos.value = inputs.ignore_after_chaos;
+    }
+
+    const gemLevelRadio = document.querySelector(`#gemLevel input[type=radio][value="${inputs.gem_level}"]`);
+    if (gemLevelRadio) gemLevelRadio.checked = true;
+    const gemQualityRadio = document.querySelector(`#gemQuality input[type=radio][value="${inputs.gem_quality}"]`);
+    if (gemQualityRadio) gemQualityRadio.checked = true;
+
+    const offerSize = document.getElementById('offerSize');
+    if (offerSize && inputs.offer_size) {
+        offerSize.value = inputs.offer_size;
+    }
+
+    const excludedGems = document.getElementById('excludedGems');
+    if (excludedGems) {
+        excludedGems.value = (inputs.exclude || '').split(',').map(name => name.trim()).filter(name => name).join(', ');
+    }
+}
+
+function showShareStatus(message) {
+    const shareStatus = document.getElementById('share-status');
+    if (!shareStatus) return;
+
+    shareStatus.textContent = message;
+    shareStatus.hidden = false;
+}
+
 function getFormData() {
     const leagueSelect = document.getElementById('league-select');
     const ignoreAfterChaos = document.getElementById('ignoreAfterChaos');
     const gemLevelRadio = document.querySelector('#gemLevel input[type=radio]:checked');
     const gemQualityRadio = document.querySelector('#gemQuality input[type=radio]:checked');
+    const offerSize = document.getElementById('offerSize');
+    const excludedGems = document.getElementById('excludedGems');
 
     return {
         league: leagueSelect ? leagueSelect.value : 'Standard',
         ignoreAfterChaos: ignoreAfterChaos ? parseFloat(ignoreAfterChaos.value) || 0 : 5,
         gemLevel: gemLevelRadio ? parseInt(gemLevelRadio.value) : 1,
-        gemQuality: gemQualityRadio ? parseInt(gemQualityRadio.value) : 0
+        gemQuality: gemQualityRadio ? parseInt(gemQualityRadio.value) : 0,
+        offerSize: offerSize ? parseInt(offerSize.value) || 3 : 3,
+        excluded: excludedGems
+            ? excludedGems.value.split(',').map(name => name.trim()).filter(name => name)
+            : []
     };
 }
 
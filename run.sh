@@ -95,11 +95,23 @@ build_release() {
     print_status "Binary location: target/release/poe-gem-calculator"
 }
 
+# Function to build the browser EV engine into public/wasm
+build_wasm() {
+    if ! command -v wasm-pack &> /dev/null; then
+        print_error "wasm-pack not found. Install it with: cargo install wasm-pack"
+        exit 1
+    fi
+
+    print_status "Building WebAssembly engine..."
+    wasm-pack build wasm --release --target web --no-typescript --out-dir ../public/wasm
+    print_success "WebAssembly engine written to public/wasm"
+}
+
 # Function to clean build artifacts
 clean() {
     print_status "Cleaning build artifacts..."
     cargo clean
-    rm -rf cache/*
+    rm -rf cache/* public/wasm
     print_success "Clean completed"
 }
 
@@ -138,6 +150,7 @@ show_help() {
     echo "  test           Run all tests"
     echo "  check          Check code quality (fmt + clippy)"
     echo "  build          Build release version"
+    echo "  wasm           Build the browser EV engine into public/wasm"
     echo "  clean          Clean build artifacts and cache"
     echo "  watch          Run with file watching (auto-restart on changes)"
     echo "  docker         Build and run with Docker"
@@ -181,6 +194,9 @@ main() {
         "build")
             build_release
             ;;
+        "wasm")
+            build_wasm
+            ;;
         "clean")
             clean
             ;;
@@ -0,0 +1,754 @@
+//! Alert rules on a color's expected value or a gem's price, evaluated every time a
+//! league's prices are refreshed, and their delivery to the configured webhooks.
+//!
+//! Rules and the delivery log are kept as JSON files in `alerts/` in the cache
+//! directory. Webhook URLs only come from the config, so API clients can't make the
+//! server send requests to hosts of their choosing. The rule routes are not
+//! authenticated; `alerts.max_rules` bounds how many rules clients can pile up.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{BTreeSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinSet;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    api::{leagues::resolve_league, skill_gems::fetch_skill_gems},
+    cache::temp_path,
+    config::AlertsConfig,
+    engine::{calculate_with_options, is_listed_variant, matches_variant, outliers::OutlierOptions, CalculationOptions},
+    models::{GemColor, SkillGemResponse},
+    AppState,
+};
+
+const RULES_FILE: &str = "rules.json";
+const DELIVERIES_FILE: &str = "deliveries.json";
+
+/// Longest cooldown a rule or `alerts.cooldown_minutes` can set, 30 days.
+pub const MAX_COOLDOWN_MINUTES: i64 = 30 * 24 * 60;
+
+/// Which side of the threshold fires the rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertComparison {
+    Above,
+    Below,
+}
+
+impl AlertComparison {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertComparison::Above => "above",
+            AlertComparison::Below => "below",
+        }
+    }
+
+    pub fn matches(&self, value: f64, threshold: f64) -> bool {
+        match self {
+            AlertComparison::Above => value > threshold,
+            AlertComparison::Below => value < threshold,
+        }
+    }
+}
+
+/// What a rule watches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertMetric {
+    /// The color's expected value or the gem's price, in chaos
+    #[default]
+    Value,
+    /// The gem's price change over the last 7 days in percent, to catch spikes
+    ChangePercent,
+}
+
+/// An alert rule as created through the API.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NewAlertRule {
+    /// Shown in deliveries (default: a description of the rule)
+    pub name: Option<String>,
+    /// League, or `current` / `current-hc` for whichever challenge league is active when prices refresh
+    pub league: String,
+    /// Color whose expected value is watched: red, green or blue. Exactly one of `color` and `gem` is set
+    pub color: Option<String>,
+    /// Gem whose price is watched, by name (case-insensitive)
+    pub gem: Option<String>,
+    #[serde(default)]
+    pub metric: AlertMetric,
+    /// Gem level to price: 1, 20 or 21 (default: 1)
+    pub gem_level: Option<u32>,
+    /// Gem quality to price: 0, 20 or 23 (default: 0)
+    pub gem_quality: Option<u32>,
+    /// Gems worth less than this count as 0 in expected values (default: `defaults.ignore_after_chaos`)
+    pub ignore_after_chaos: Option<f64>,
+    /// Gem names or `*` patterns left out of expected values
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    pub comparison: AlertComparison,
+    /// Chaos, or percent for `change_percent`
+    pub threshold: f64,
+    /// Minutes after firing before the rule can fire again, at most 30 days (default: `alerts.cooldown_minutes`)
+    pub cooldown_minutes: Option<i64>,
+}
+
+/// A persisted alert rule.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AlertRule {
+    pub id: String,
+    pub name: String,
+    pub league: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gem: Option<String>,
+    pub metric: AlertMetric,
+    pub gem_level: u32,
+    pub gem_quality: u32,
+    /// Null for `defaults.ignore_after_chaos`
+    pub ignore_after_chaos: Option<f64>,
+    pub exclude: Vec<String>,
+    pub comparison: AlertComparison,
+    pub threshold: f64,
+    pub cooldown_minutes: i64,
+    pub created_at: DateTime<Utc>,
+    /// When the rule was last evaluated on fresh prices
+    pub last_evaluated_at: Option<DateTime<Utc>>,
+    /// The watched value at the last evaluation, null when it had no price
+    pub last_value: Option<f64>,
+    pub last_fired_at: Option<DateTime<Utc>>,
+}
+
+impl NewAlertRule {
+    /// Validates the rule and fills in the defaults.
+    pub fn into_rule(self, config: &AlertsConfig) -> Result<AlertRule, String> {
+        if self.league.trim().is_empty() {
+            return Err("league must not be empty".to_string());
+        }
+        let color = match (&self.color, &self.gem) {
+            (Some(color), None) => Some(
+                parse_color(color)
+                    .ok_or_else(|| format!("color must be red, green or blue, got {:?}", color))?
+                    .as_str()
+                    .to_string(),
+            ),
+            (None, Some(gem)) if !gem.trim().is_empty() => None,
+            _ => return Err("exactly one of color and gem must be set".to_string()),
+        };
+        if color.is_some() && self.metric == AlertMetric::ChangePercent {
+            return Err("change_percent can only watch a gem".to_string());
+        }
+        if !self.threshold.is_finite() {
+            return Err("threshold must be a number".to_string());
+        }
+        if self.ignore_after_chaos.is_some_and(|chaos| !chaos.is_finite() || chaos < 0.0) {
+            return Err("ignore_after_chaos must be zero or positive".to_string());
+        }
+        let gem_level = self.gem_level.unwrap_or(1);
+        let gem_quality = self.gem_quality.unwrap_or(0);
+        if !is_listed_variant(gem_level, gem_quality) {
+            return Err(format!(
+                "POE Ninja only lists gem_level 1, 20 or 21 with gem_quality 0, 20 or 23, got {}/{}",
+                gem_level, gem_quality
+            ));
+        }
+        let cooldown_minutes = self.cooldown_minutes.unwrap_or(config.cooldown_minutes);
+        if !(0..=MAX_COOLDOWN_MINUTES).contains(&cooldown_minutes) {
+            return Err(format!("cooldown_minutes must be between 0 and {}", MAX_COOLDOWN_MINUTES));
+        }
+
+        let mut rule = AlertRule {
+            id: Uuid::new_v4().to_string(),
+            name: String::new(),
+            league: self.league.trim().to_string(),
+            color,
+            gem: self.gem.map(|gem| gem.trim().to_string()),
+            metric: self.metric,
+            gem_level,
+            gem_quality,
+            ignore_after_chaos: self.ignore_after_chaos,
+            exclude: self.exclude,
+            comparison: self.comparison,
+            threshold: self.threshold,
+            cooldown_minutes,
+            created_at: Utc::now(),
+            last_evaluated_at: None,
+            last_value: None,
+            last_fired_at: None,
+        };
+        rule.name = match self.name.filter(|name| !name.trim().is_empty()) {
+            Some(name) => name,
+            None => format!(
+                "{} {} {}{}",
+                rule.subject(&rule.league),
+                rule.comparison.as_str(),
+                rule.threshold,
+                rule.unit()
+            ),
+        };
+        Ok(rule)
+    }
+}
+
+impl AlertRule {
+    /// The watched value in a price snapshot, None when it has no price there.
+    pub fn current_value(
+        &self,
+        skill_gems_response: &SkillGemResponse,
+        ignore_after_chaos: f64,
+        outliers: &OutlierOptions,
+    ) -> Option<f64> {
+        if let Some(color) = self.color.as_deref().and_then(parse_color) {
+            let options = CalculationOptions {
+                gem_level: self.gem_level,
+                gem_quality: self.gem_quality,
+                ignore_after_chaos: self.ignore_after_chaos.unwrap_or(ignore_after_chaos),
+                excluded: self.exclude.clone(),
+                outliers: outliers.clone(),
+                ..CalculationOptions::default()
+            };
+            let response = calculate_with_options(skill_gems_response, &options);
+            return Some(match color {
+                GemColor::Red => response.red_roi,
+                GemColor::Green => response.green_roi,
+                GemColor::Blue => response.blue_roi,
+            });
+        }
+
+        let name = self.gem.as_deref()?;
+        let gem = skill_gems_response
+            .lines
+            .iter()
+            .find(|gem| gem.name.eq_ignore_ascii_case(name) && matches_variant(gem, self.gem_level, self.gem_quality))?;
+        match self.metric {
+            AlertMetric::Value => gem.chaos_value,
+            AlertMetric::ChangePercent => gem.sparkline.as_ref().and_then(|sparkline| sparkline.total_change),
+        }
+    }
+
+    /// Whether a value fires the rule, given when it last fired. A cooldown too long
+    /// to add to the time it fired never ends.
+    pub fn should_fire(&self, value: f64, now: DateTime<Utc>) -> bool {
+        let cooled_down = self.last_fired_at.is_none_or(|fired| {
+            Duration::try_minutes(self.cooldown_minutes)
+                .and_then(|cooldown| fired.checked_add_signed(cooldown))
+                .is_some_and(|until| until <= now)
+        });
+        cooled_down && self.comparison.matches(value, self.threshold)
+    }
+
+    /// One line describing the value for the webhook message.
+    pub fn message(&self, league: &str, value: Option<f64>) -> String {
+        let value = match value {
+            Some(value) if self.metric == AlertMetric::ChangePercent => format!("changed {:.0}% over 7 days", value),
+            Some(value) => format!("is {:.2}c", value),
+            None => "has no price".to_string(),
+        };
+        format!(
+            "{} {}, {} the {}{} threshold ({})",
+            self.subject(league),
+            value,
+            self.comparison.as_str(),
+            self.threshold,
+            self.unit(),
+            self.name
+        )
+    }
+
+    fn subject(&self, league: &str) -> String {
+        let variant = format!("{}/{}", self.gem_level, self.gem_quality);
+        match (&self.color, &self.gem) {
+            (Some(color), _) => {
+                let mut title = color.clone();
+                title[..1].make_ascii_uppercase();
+                format!("{} {} expected value in {}", title, variant, league)
+            }
+            (None, Some(gem)) => format!("{} {} in {}", gem, variant, league),
+            (None, None) => league.to_string(),
+        }
+    }
+
+    fn unit(&self) -> &'static str {
+        match self.metric {
+            AlertMetric::Value => "c",
+            AlertMetric::ChangePercent => "%",
+        }
+    }
+}
+
+/// JSON body POSTed to every webhook when a rule fires.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AlertPayload {
+    /// `alert` when the rule fired, `test` for test deliveries
+    pub event: String,
+    /// Human-readable summary; Discord webhooks show it as the message
+    pub content: String,
+    /// The league the rule was evaluated in, with `current` resolved
+    pub league: String,
+    /// The watched value, null when it has no price
+    pub value: Option<f64>,
+    /// When the prices were fetched from POE Ninja
+    pub prices_updated_at: Option<DateTime<Utc>>,
+    pub fired_at: DateTime<Utc>,
+    pub rule: AlertRule,
+}
+
+impl AlertPayload {
+    pub fn new(event: &str, rule: &AlertRule, league: &str, value: Option<f64>, prices_updated_at: Option<DateTime<Utc>>) -> Self {
+        Self {
+            event: event.to_string(),
+            content: rule.message(league, value),
+            league: league.to_string(),
+            value,
+            prices_updated_at,
+            fired_at: Utc::now(),
+            rule: rule.clone(),
+        }
+    }
+}
+
+/// One webhook delivery of an alert, after all its attempts.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AlertDelivery {
+    pub id: String,
+    pub rule_id: String,
+    pub event: String,
+    /// Scheme and host of the webhook; the path is left out as it often holds a token
+    pub webhook: String,
+    pub delivered: bool,
+    pub attempts: u32,
+    /// HTTP status of the last attempt, null when it got no response
+    pub status: Option<u16>,
+    /// Why the last attempt failed
+    pub error: Option<String>,
+    pub value: Option<f64>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+}
+
+/// Alert rules and the most recent deliveries, persisted as JSON files.
+pub struct AlertStore {
+    dir: PathBuf,
+    rules: Mutex<Vec<AlertRule>>,
+    deliveries: Mutex<VecDeque<AlertDelivery>>,
+    log_size: usize,
+    max_rules: usize,
+}
+
+impl AlertStore {
+    /// Opens the store in a directory, creating it if needed. `log_size` is the
+    /// number of deliveries kept, `max_rules` the number of rules that can be added.
+    pub fn open<P: AsRef<Path>>(dir: P, log_size: usize, max_rules: usize) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create alerts directory: {:?}", dir))?;
+
+        let rules = read_json(&dir.join(RULES_FILE))?.unwrap_or_default();
+        let deliveries = read_json(&dir.join(DELIVERIES_FILE))?.unwrap_or_default();
+
+        Ok(Self {
+            dir,
+            rules: Mutex::new(rules),
+            deliveries: Mutex::new(deliveries),
+            log_size,
+            max_rules,
+        })
+    }
+
+    pub fn rules(&self) -> Vec<AlertRule> {
+        self.rules.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn rule(&self, id: &str) -> Option<AlertRule> {
+        self.rules().into_iter().find(|rule| rule.id == id)
+    }
+
+    /// Adds a rule, returning false when the store already holds `max_rules`.
+    pub fn add(&self, rule: AlertRule) -> Result<bool> {
+        let mut rules = self.rules.lock().unwrap_or_else(|e| e.into_inner());
+        if rules.len() >= self.max_rules {
+            return Ok(false);
+        }
+        rules.push(rule);
+        write_json(&self.dir.join(RULES_FILE), &*rules)?;
+        Ok(true)
+    }
+
+    /// Deletes a rule, returning whether it existed.
+    pub fn remove(&self, id: &str) -> Result<bool> {
+        let mut rules = self.rules.lock().unwrap_or_else(|e| e.into_inner());
+        let count = rules.len();
+        rules.retain(|rule| rule.id != id);
+        if rules.len() == count {
+            return Ok(false);
+        }
+        write_json(&self.dir.join(RULES_FILE), &*rules)?;
+        Ok(true)
+    }
+
+    /// Records an evaluation, and that the rule fired if `fired`.
+    fn record_evaluation(&self, id: &str, value: Option<f64>, at: DateTime<Utc>, fired: bool) -> Result<()> {
+        let mut rules = self.rules.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(rule) = rules.iter_mut().find(|rule| rule.id == id) else {
+            return Ok(());
+        };
+        rule.last_evaluated_at = Some(at);
+        rule.last_value = value;
+        if fired {
+            rule.last_fired_at = Some(at);
+        }
+        write_json(&self.dir.join(RULES_FILE), &*rules)
+    }
+
+    /// Deliveries newest first, optionally of one rule only.
+    pub fn deliveries(&self, rule_id: Option<&str>) -> Vec<AlertDelivery> {
+        self.deliveries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .rev()
+            .filter(|delivery| rule_id.is_none_or(|id| delivery.rule_id == id))
+            .cloned()
+            .collect()
+    }
+
+    fn record_delivery(&self, delivery: AlertDelivery) -> Result<()> {
+        let mut deliveries = self.deliveries.lock().unwrap_or_else(|e| e.into_inner());
+        deliveries.push_back(delivery);
+        while deliveries.len() > self.log_size {
+            deliveries.pop_front();
+        }
+        write_json(&self.dir.join(DELIVERIES_FILE), &*deliveries)
+    }
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+    let value = serde_json::from_str(&content).with_context(|| format!("Failed to parse {:?}", path))?;
+    Ok(Some(value))
+}
+
+/// Writes to a temporary file and renames it into place, like the cache does.
+fn write_json<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<()> {
+    let temp_path = temp_path(path);
+    let content = serde_json::to_string_pretty(value)?;
+    fs::write(&temp_path, content).with_context(|| format!("Failed to write {:?}", temp_path))?;
+    fs::rename(&temp_path, path).with_context(|| format!("Failed to move {:?} into place", path))?;
+    Ok(())
+}
+
+fn parse_color(color: &str) -> Option<GemColor> {
+    [GemColor::Red, GemColor::Green, GemColor::Blue]
+        .into_iter()
+        .find(|gem_color| gem_color.as_str().eq_ignore_ascii_case(color))
+}
+
+/// Evaluates every rule of a league on its freshly fetched prices and returns the
+/// payloads of the ones that fire, to be delivered.
+pub async fn evaluate(state: &AppState, league: &str, prices_updated_at: DateTime<Utc>) -> Vec<AlertPayload> {
+    let mut snapshot = None;
+    let mut fired_payloads = Vec::new();
+
+    for rule in state.alerts.rules() {
+        if resolve_league(state, &rule.league).await != league {
+            continue;
+        }
+        if snapshot.is_none() {
+            snapshot = match state.cache.get::<SkillGemResponse>(&format!("skillGems_{}", league)).await {
+                Ok(Some(skill_gems_response)) => Some(skill_gems_response),
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("Failed to load prices of {} for alerts: {}", league, e);
+                    break;
+                }
+            };
+        }
+        let Some(skill_gems_response) = &snapshot else {
+            break;
+        };
+
+        let now = Utc::now();
+        let value = rule.current_value(
+            skill_gems_response,
+            state.config.defaults.ignore_after_chaos,
+            &state.config.outliers,
+        );
+        let fired = value.is_some_and(|value| rule.should_fire(value, now));
+        if let Err(e) = state.alerts.record_evaluation(&rule.id, value, now, fired) {
+            error!("Failed to save evaluation of alert {}: {}", rule.id, e);
+        }
+
+        if fired {
+            info!("Alert {} fired: {}", rule.id, rule.message(league, value));
+            fired_payloads.push(AlertPayload::new("alert", &rule, league, value, Some(prices_updated_at)));
+        }
+    }
+
+    fired_payloads
+}
+
+/// POSTs a payload to every configured webhook, retrying failed attempts up to
+/// `retries` times with exponential backoff, and records each delivery in the log.
+pub async fn deliver(state: &AppState, payload: &AlertPayload, retries: u32) -> Vec<AlertDelivery> {
+    let config = &state.config.alerts;
+    let mut deliveries = Vec::new();
+
+    for url in &config.webhook_urls {
+        let started_at = Utc::now();
+        let mut attempts = 0;
+        let (delivered, status, error) = loop {
+            attempts += 1;
+            let (delivered, status, error) = match state.client.post(url).json(payload).send().await {
+                Ok(response) if response.status().is_success() => (true, Some(response.status().as_u16()), None),
+                Ok(response) => (
+                    false,
+                    Some(response.status().as_u16()),
+                    Some(format!("webhook returned {}", response.status())),
+                ),
+                Err(e) => (false, None, Some(e.to_string())),
+            };
+            if delivered || attempts > retries || state.shutdown.is_triggered() {
+                break (delivered, status, error);
+            }
+
+            let delay = std::time::Duration::from_secs(config.retry_delay_seconds.saturating_mul(1 << (attempts - 1).min(16)));
+            warn!(
+                "Alert delivery to {} failed (attempt {}): {}; retrying in {}s",
+                redact_url(url),
+                attempts,
+                error.as_deref().unwrap_or("unknown error"),
+                delay.as_secs()
+            );
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = state.shutdown.wait() => break (delivered, status, error),
+            }
+        };
+
+        let delivery = AlertDelivery {
+            id: Uuid::new_v4().to_string(),
+            rule_id: payload.rule.id.clone(),
+            event: payload.event.clone(),
+            webhook: redact_url(url),
+            delivered,
+            attempts,
+            status,
+            error,
+            value: payload.value,
+            started_at,
+            finished_at: Utc::now(),
+        };
+        if !delivered {
+            warn!("Alert delivery to {} failed after {} attempts", delivery.webhook, attempts);
+        }
+        state.metrics.observe_alert_delivery(delivered);
+        if let Err(e) = state.alerts.record_delivery(delivery.clone()) {
+            error!("Failed to log alert delivery: {}", e);
+        }
+        deliveries.push(delivery);
+    }
+
+    deliveries
+}
+
+/// Scheme, host and port of a URL.
+fn redact_url(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) => parsed.origin().ascii_serialization(),
+        Err(_) => "invalid URL".to_string(),
+    }
+}
+
+/// Evaluates alert rules whenever prices are refreshed, until shutdown.
+///
+/// Leagues with rules are also checked every `alerts.refresh_minutes`, so their
+/// prices are refreshed once the cached ones expire even if nobody asks for them.
+/// Deliveries run as tasks of their own, so a slow or failing webhook doesn't hold
+/// up evaluation; on shutdown they stop retrying and are awaited.
+pub async fn run(state: AppState) {
+    let mut updates = state.snapshot_updates.subscribe();
+    let mut deliveries = JoinSet::new();
+    let mut refresh = tokio::time::interval(std::time::Duration::from_secs(state.config.alerts.refresh_minutes * 60));
+    refresh.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    if state.config.alerts.webhook_urls.is_empty() && !state.alerts.rules().is_empty() {
+        warn!("Alert rules are set up but alerts.webhook_urls is empty, so nothing will be delivered");
+    }
+
+    loop {
+        tokio::select! {
+            update = updates.recv() => match update {
+                Ok(update) => {
+                    for payload in evaluate(&state, &update.league, update.updated_at).await {
+                        let state = state.clone();
+                        deliveries.spawn(async move {
+                            deliver(&state, &payload, state.config.alerts.retries).await;
+                        });
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => warn!("Alert evaluation skipped {} price updates", skipped),
+                Err(RecvError::Closed) => break,
+            },
+            Some(_) = deliveries.join_next() => {}
+            _ = refresh.tick() => {
+                let mut leagues = BTreeSet::new();
+                for rule in state.alerts.rules() {
+                    leagues.insert(resolve_league(&state, &rule.league).await);
+                }
+                for league in leagues {
+                    // A fetch of expired prices announces the update, which evaluates the rules
+                    if let Err(status) = fetch_skill_gems(&state, &league).await {
+                        warn!("Failed to refresh prices of {} for alerts: {}", league, status);
+                    }
+                }
+            }
+            _ = state.shutdown.wait() => break,
+        }
+    }
+
+    while deliveries.join_next().await.is_some() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+    use std::sync::Arc;
+
+    fn overview() -> SkillGemResponse {
+        serde_json::from_value(serde_json::json!({ "lines": [
+            {
+                "name": "Spark of Unpredictability",
+                "icon": "https://web.poecdn.com/gen/image/WzMwLDE0LHsiZ2QiOjE0fV0/x/Spark.png",
+                "chaosValue": 200.0,
+                "sparkline": { "data": [0.0, 300.0], "totalChange": 300.0 },
+                "tradeFilter": {}
+            }
+        ] }))
+        .unwrap()
+    }
+
+    fn new_rule(value: serde_json::Value) -> Result<AlertRule, String> {
+        serde_json::from_value::<NewAlertRule>(value).unwrap().into_rule(&AlertsConfig::default())
+    }
+
+    #[test]
+    fn test_new_alert_rule() {
+        let rule = new_rule(serde_json::json!({
+            "league": "current", "color": "Blue", "gem_level": 20, "gem_quality": 20,
+            "comparison": "above", "threshold": 150.0
+        }))
+        .unwrap();
+        assert_eq!(rule.color.as_deref(), Some("blue"));
+        assert_eq!(rule.name, "Blue 20/20 expected value in current above 150c");
+        assert_eq!(rule.cooldown_minutes, AlertsConfig::default().cooldown_minutes);
+
+        let both = serde_json::json!({ "league": "Standard", "color": "red", "gem": "Spark", "comparison": "above", "threshold": 1.0 });
+        assert!(new_rule(both).is_err());
+        let change = serde_json::json!({ "league": "Standard", "color": "red", "metric": "change_percent", "comparison": "above", "threshold": 1.0 });
+        assert!(new_rule(change).is_err());
+        let unlisted = serde_json::json!({ "league": "Standard", "color": "red", "gem_level": 4000000000u32, "comparison": "above", "threshold": 1.0 });
+        assert!(new_rule(unlisted).is_err());
+        let forever = serde_json::json!({ "league": "Standard", "color": "red", "comparison": "above", "threshold": 1.0, "cooldown_minutes": i64::MAX });
+        assert!(new_rule(forever).is_err());
+    }
+
+    #[test]
+    fn test_alert_rule_values() {
+        let overview = overview();
+        let outliers = OutlierOptions::default();
+        let gem = new_rule(serde_json::json!({
+            "league": "Standard", "gem": "spark of unpredictability", "comparison": "above", "threshold": 150.0
+        }))
+        .unwrap();
+        assert_eq!(gem.current_value(&overview, 5.0, &outliers), Some(200.0));
+
+        let spike = AlertRule { metric: AlertMetric::ChangePercent, ..gem.clone() };
+        assert_eq!(spike.current_value(&overview, 5.0, &outliers), Some(300.0));
+
+        let now = Utc::now();
+        assert!(gem.should_fire(200.0, now));
+        assert!(!gem.should_fire(100.0, now));
+        let cooling = AlertRule { last_fired_at: Some(now - Duration::minutes(10)), ..gem };
+        assert!(!cooling.should_fire(200.0, now));
+        assert!(cooling.should_fire(200.0, now + Duration::minutes(60)));
+
+        // A stored cooldown too long to add never ends, rather than panicking
+        let endless = AlertRule { cooldown_minutes: i64::MAX, ..cooling };
+        assert!(!endless.should_fire(200.0, now + Duration::days(365)));
+    }
+
+    /// Webhook sink that fails the first request and records every payload.
+    async fn start_sink() -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let app = Router::new()
+            .route(
+                "/hook/secret-token",
+                post(|State(received): State<Arc<Mutex<Vec<serde_json::Value>>>>, Json(body): Json<serde_json::Value>| async move {
+                    let mut received = received.lock().unwrap();
+                    received.push(body);
+                    if received.len() == 1 { StatusCode::INTERNAL_SERVER_ERROR } else { StatusCode::NO_CONTENT }
+                }),
+            )
+            .with_state(received.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}/hook/secret-token", address), received)
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_and_deliver() {
+        let (url, received) = start_sink().await;
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = crate::config::Config::default();
+        config.cache.dir = temp_dir.path().to_str().unwrap().to_string();
+        config.alerts.webhook_urls = vec![url];
+        config.alerts.retry_delay_seconds = 0;
+        let state = AppState::from_config(config).unwrap();
+
+        state.cache.set("skillGems_Standard", overview(), 60).await.unwrap();
+        let rule = new_rule(serde_json::json!({
+            "league": "Standard", "gem": "Spark of Unpredictability", "comparison": "above", "threshold": 150.0
+        }))
+        .unwrap();
+        assert!(state.alerts.add(rule.clone()).unwrap());
+
+        let fired = evaluate(&state, "Standard", Utc::now()).await;
+        assert_eq!(fired.len(), 1);
+        deliver(&state, &fired[0], state.config.alerts.retries).await;
+        // Cooling down, so the next refresh doesn't fire again
+        assert!(evaluate(&state, "Standard", Utc::now()).await.is_empty());
+        // Rules of other leagues are left alone
+        assert!(evaluate(&state, "Hardcore", Utc::now()).await.is_empty());
+
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 2, "the failed first attempt is retried");
+        assert_eq!(received[1]["event"], "alert");
+        assert_eq!(received[1]["value"], 200.0);
+        assert_eq!(received[1]["rule"]["id"], rule.id.as_str());
+
+        let deliveries = state.alerts.deliveries(Some(&rule.id));
+        assert_eq!(deliveries.len(), 1);
+        assert!(deliveries[0].delivered);
+        assert_eq!(deliveries[0].attempts, 2);
+        assert_eq!(deliveries[0].status, Some(204));
+        assert!(!deliveries[0].webhook.contains("secret-token"));
+
+        // Rules and the log survive a restart
+        let reopened = AlertStore::open(temp_dir.path().join("alerts"), 10, 1).unwrap();
+        assert!(reopened.rule(&rule.id).unwrap().last_fired_at.is_some());
+        assert_eq!(reopened.deliveries(None).len(), 1);
+        // It already holds as many rules as it may
+        assert!(!reopened.add(rule).unwrap());
+    }
+}
@@ -0,0 +1,180 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json},
+};
+use serde::Deserialize;
+use tracing::{error, info, warn};
+use utoipa::IntoParams;
+
+use crate::{
+    alerts::{deliver, AlertDelivery, AlertPayload, AlertRule, NewAlertRule},
+    api::leagues::resolve_league,
+    models::SkillGemResponse,
+    AppState,
+};
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AlertDeliveriesQuery {
+    /// Only deliveries of this rule
+    rule_id: Option<String>,
+}
+
+/// Lists the alert rules with their last evaluation.
+#[utoipa::path(
+    get,
+    path = "/api/v1/alerts",
+    tag = "alerts",
+    responses(
+        (status = 200, description = "Alert rules, oldest first", body = [AlertRule])
+    )
+)]
+pub async fn list_alert_rules(State(state): State<AppState>) -> Json<Vec<AlertRule>> {
+    Json(state.alerts.rules())
+}
+
+/// Creates an alert rule on a color's expected value or a gem's price.
+///
+/// Rules are evaluated every time their league's prices are refreshed. A rule that
+/// fires is POSTed to every `alerts.webhook_urls` and then stays quiet for its cooldown.
+/// Like every alert route this one is not authenticated, so anyone who can reach the
+/// server can add rules, up to `alerts.max_rules`.
+#[utoipa::path(
+    post,
+    path = "/api/v1/alerts",
+    tag = "alerts",
+    request_body = NewAlertRule,
+    responses(
+        (status = 201, description = "The created rule; Location has its URL", body = AlertRule),
+        (status = 400, description = "Invalid rule"),
+        (status = 409, description = "There are already `alerts.max_rules` rules"),
+        (status = 422, description = "Malformed rule JSON"),
+        (status = 500, description = "The rule could not be saved")
+    )
+)]
+pub async fn create_alert_rule(
+    State(state): State<AppState>,
+    Json(new_rule): Json<NewAlertRule>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let rule = new_rule.into_rule(&state.config.alerts).map_err(|e| {
+        warn!("Rejecting alert rule: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+
+    match state.alerts.add(rule.clone()) {
+        Ok(true) => {}
+        Ok(false) => {
+            warn!("Rejecting alert rule: already {} rules", state.config.alerts.max_rules);
+            return Err(StatusCode::CONFLICT);
+        }
+        Err(e) => {
+            error!("Failed to save alert rule: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    info!("Created alert rule {}: {}", rule.id, rule.name);
+
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, format!("/api/v1/alerts/{}", rule.id))],
+        Json(rule),
+    ))
+}
+
+/// Returns one alert rule.
+#[utoipa::path(
+    get,
+    path = "/api/v1/alerts/{id}",
+    tag = "alerts",
+    params(("id" = String, Path, description = "Id of the alert rule")),
+    responses(
+        (status = 200, description = "Alert rule", body = AlertRule),
+        (status = 404, description = "No alert rule with this id")
+    )
+)]
+pub async fn get_alert_rule(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<AlertRule>, StatusCode> {
+    state.alerts.rule(&id).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Deletes an alert rule. Its deliveries stay in the log.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/alerts/{id}",
+    tag = "alerts",
+    params(("id" = String, Path, description = "Id of the alert rule")),
+    responses(
+        (status = 204, description = "Rule deleted"),
+        (status = 404, description = "No alert rule with this id"),
+        (status = 500, description = "The rules could not be saved")
+    )
+)]
+pub async fn delete_alert_rule(Path(id): Path<String>, State(state): State<AppState>) -> StatusCode {
+    match state.alerts.remove(&id) {
+        Ok(true) => {
+            info!("Deleted alert rule {}", id);
+            StatusCode::NO_CONTENT
+        }
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            error!("Failed to delete alert rule {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Sends a `test` delivery of a rule to every webhook now, whatever the prices and
+/// cooldown, with the value in the cached prices if there are any.
+///
+/// Each webhook gets a single attempt, without the retries of real deliveries, so
+/// the response reports right away whether the webhooks work.
+#[utoipa::path(
+    post,
+    path = "/api/v1/alerts/{id}/test",
+    tag = "alerts",
+    params(("id" = String, Path, description = "Id of the alert rule")),
+    responses(
+        (status = 200, description = "One delivery attempt per configured webhook; empty without webhooks", body = [AlertDelivery]),
+        (status = 404, description = "No alert rule with this id")
+    )
+)]
+pub async fn test_alert_rule(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AlertDelivery>>, StatusCode> {
+    let rule = state.alerts.rule(&id).ok_or(StatusCode::NOT_FOUND)?;
+    let league = resolve_league(&state, &rule.league).await;
+
+    let cached = state
+        .cache
+        .get_with_timestamp::<SkillGemResponse>(&format!("skillGems_{}", league))
+        .await
+        .ok()
+        .flatten();
+    let value = cached.as_ref().and_then(|(skill_gems_response, _)| {
+        rule.current_value(skill_gems_response, state.config.defaults.ignore_after_chaos, &state.config.outliers)
+    });
+
+    let payload = AlertPayload::new("test", &rule, &league, value, cached.map(|(_, updated_at)| updated_at));
+    Ok(Json(deliver(&state, &payload, 0).await))
+}
+
+/// Most recent alert deliveries, newest first, kept up to `alerts.delivery_log_size`.
+#[utoipa::path(
+    get,
+    path = "/api/v1/alerts/deliveries",
+    tag = "alerts",
+    params(AlertDeliveriesQuery),
+    responses(
+        (status = 200, description = "Delivery log, newest first", body = [AlertDelivery])
+    )
+)]
+pub async fn list_alert_deliveries(
+    Query(params): Query<AlertDeliveriesQuery>,
+    State(state): State<AppState>,
+) -> Json<Vec<AlertDelivery>> {
+    Json(state.alerts.deliveries(params.rule_id.as_deref()))
+}
@@ -0,0 +1,83 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use tracing::{info, warn};
+use utoipa::IntoParams;
+
+use crate::{
+    api::{currency::input_price, leagues::resolve_league, skill_gems::fetch_skill_gems},
+    engine::corruption::corruption_values,
+    models::CorruptionResponse,
+    AppState,
+};
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CorruptionQuery {
+    /// League to fetch gem prices for, or `current` / `current-hc` for the active challenge league (default: `defaults.league`)
+    league: Option<String>,
+    /// Vaal Orb price in chaos, counted against each gem's profit (default: the poe.ninja price, or `defaults.vaal_orb_chaos` without one)
+    vaal_orb_chaos: Option<f64>,
+    /// Only include gems of this color: red, green or blue
+    color: Option<String>,
+}
+
+/// Expected value of corrupting each 20/20 transfigured gem with a Vaal Orb.
+///
+/// Outcomes are weighted with the `corruption` probabilities from the config and
+/// priced with poe.ninja's corrupted listings. `corrupt` lists the gems whose
+/// expected value beats the price of the gem plus the Vaal Orb.
+#[utoipa::path(
+    get,
+    path = "/api/v1/corruption",
+    tag = "calculation",
+    params(CorruptionQuery),
+    responses(
+        (status = 200, description = "Corruption expected value per gem", body = CorruptionResponse),
+        (status = 400, description = "Negative or non-finite Vaal Orb price"),
+        (status = 404, description = "POE Ninja has no data for the league"),
+        (status = 429, description = "Too many requests from this client or to POE Ninja; see Retry-After"),
+        (status = 502, description = "POE Ninja returned an error"),
+        (status = 500, description = "POE Ninja could not be reached or parsed")
+    )
+)]
+pub async fn get_corruption_values(
+    Query(params): Query<CorruptionQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<CorruptionResponse>, StatusCode> {
+    if let Some(price) = params.vaal_orb_chaos.filter(|price| !price.is_finite() || *price < 0.0) {
+        warn!("Rejecting Vaal Orb price: {}", price);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let league = params.league.unwrap_or_else(|| state.config.defaults.league.clone());
+    let league = resolve_league(&state, &league).await;
+    let skill_gems_response = fetch_skill_gems(&state, &league).await?;
+    let vaal_orb_chaos = match params.vaal_orb_chaos {
+        Some(price) => price,
+        None => input_price(&state, &league, "Vaal Orb", state.config.defaults.vaal_orb_chaos).await,
+    };
+
+    info!("Calculating corruption values for league: {}, vaal orb: {}c", league, vaal_orb_chaos);
+
+    let mut gems = corruption_values(&skill_gems_response, &state.config.corruption, vaal_orb_chaos);
+    if let Some(color) = &params.color {
+        gems.retain(|gem| gem.color.as_deref().is_some_and(|gem_color| gem_color.eq_ignore_ascii_case(color)));
+    }
+
+    let corrupt = gems
+        .iter()
+        .filter(|gem| gem.profit > 0.0)
+        .map(|gem| gem.name.clone())
+        .collect();
+
+    Ok(Json(CorruptionResponse {
+        league,
+        vaal_orb_chaos,
+        gems,
+        corrupt,
+    }))
+}
@@ -0,0 +1,140 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use std::time::Instant;
+use tracing::{error, info, warn};
+use utoipa::IntoParams;
+
+use crate::{
+    api::{leagues::resolve_league, skill_gems::request_overview},
+    models::CurrencyOverviewResponse,
+    upstream::Upstream,
+    AppState,
+};
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CurrencyQuery {
+    /// League to fetch currency prices for, or `current` / `current-hc` for the active challenge league (default: `defaults.league`)
+    league: Option<String>,
+}
+
+/// Returns the raw currency overview from POE Ninja for a league.
+#[utoipa::path(
+    get,
+    path = "/api/v1/currency",
+    tag = "gems",
+    params(CurrencyQuery),
+    responses(
+        (status = 200, description = "Currency overview", body = CurrencyOverviewResponse),
+        (status = 404, description = "POE Ninja has no data for the league"),
+        (status = 429, description = "Too many requests from this client or to POE Ninja; see Retry-After"),
+        (status = 502, description = "POE Ninja returned an error"),
+        (status = 500, description = "POE Ninja could not be reached or parsed")
+    )
+)]
+pub async fn get_currency(
+    Query(params): Query<CurrencyQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<CurrencyOverviewResponse>, StatusCode> {
+    let league = params.league.unwrap_or_else(|| state.config.defaults.league.clone());
+    let league = resolve_league(&state, &league).await;
+
+    Ok(Json(fetch_currency(&state, &league).await?))
+}
+
+/// Loads the currency overview for a league, serving it from the cache when possible.
+///
+/// Cached and answered like `fetch_skill_gems`, except that a league without
+/// currency data isn't remembered as unknown.
+pub async fn fetch_currency(state: &AppState, league: &str) -> Result<CurrencyOverviewResponse, StatusCode> {
+    let cache_key = format!("currency_{}", league);
+
+    if let Ok(Some(cached)) = state.cache.get::<CurrencyOverviewResponse>(&cache_key).await {
+        info!("Returning cached currency data for league: {}", league);
+        return Ok(cached);
+    }
+
+    if let Ok(Some(true)) = state.cache.get::<bool>(&format!("unknownLeague_{}", league)).await {
+        info!("League {} is known to have no data on POE Ninja", league);
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    if state.rate_limits.upstream.check(&()).is_err() {
+        warn!("Upstream request budget used up, not fetching currency for league: {}", league);
+        state.metrics.observe_rate_limited("upstream");
+        return Err(StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    info!("Fetching fresh currency data for league: {}", league);
+
+    let started = Instant::now();
+    let result = request_overview::<CurrencyOverviewResponse>(state, "currencyoverview?type=Currency", league).await;
+    let answered = match &result {
+        Ok(_) => true,
+        Err((status, _)) => *status == StatusCode::NOT_FOUND,
+    };
+    state
+        .metrics
+        .observe_upstream(Upstream::PoeNinja, started.elapsed(), answered);
+
+    let currency_response = match result {
+        Ok(data) if !data.lines.is_empty() => {
+            state.upstreams.record_success(Upstream::PoeNinja);
+            data
+        }
+        Ok(_) | Err((StatusCode::NOT_FOUND, _)) => {
+            state.upstreams.record_success(Upstream::PoeNinja);
+            warn!("POE Ninja has no currency data for league: {}", league);
+            return Err(StatusCode::NOT_FOUND);
+        }
+        Err((status, message)) => {
+            error!("{}", message);
+            state.upstreams.record_failure(Upstream::PoeNinja, message);
+            return Err(status);
+        }
+    };
+
+    if let Err(e) = state.cache.set(&cache_key, &currency_response, state.config.cache.ttl_minutes).await {
+        warn!("Failed to cache currency data: {}", e);
+    }
+
+    info!(
+        "Successfully fetched and cached {} currency prices for league: {}",
+        currency_response.lines.len(),
+        league
+    );
+
+    Ok(currency_response)
+}
+
+/// Chaos price of a crafting input such as "Vaal Orb" in a league.
+///
+/// Falls back to `default_chaos` when the currency data can't be loaded or doesn't
+/// list the currency, so a calculation never fails just because of its inputs.
+pub async fn input_price(state: &AppState, league: &str, currency: &str, default_chaos: f64) -> f64 {
+    match fetch_currency(state, league).await {
+        Ok(overview) => overview.chaos_equivalent(currency).unwrap_or_else(|| {
+            warn!("POE Ninja has no {} price in {}, using {}c", currency, league, default_chaos);
+            default_chaos
+        }),
+        Err(status) => {
+            warn!("No currency prices for {} ({}), using {}c for {}", league, status, default_chaos, currency);
+            default_chaos
+        }
+    }
+}
+
+/// Full name and chaos price of a currency requested by short or full name.
+///
+/// Unknown currencies are a bad request; failures to load the currency data are
+/// passed on like those of `fetch_skill_gems`.
+pub async fn conversion_rate(state: &AppState, league: &str, currency: &str) -> Result<(String, f64), StatusCode> {
+    let overview = fetch_currency(state, league).await?;
+    let name = overview.currency_name(currency).ok_or(StatusCode::BAD_REQUEST)?;
+    let chaos_equivalent = overview.chaos_equivalent(&name).ok_or(StatusCode::NOT_FOUND)?;
+    Ok((name, chaos_equivalent))
+}
@@ -0,0 +1,174 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use std::collections::HashSet;
+use utoipa::IntoParams;
+use tracing::info;
+
+use crate::{
+    api::{leagues::resolve_league, skill_gems::fetch_skill_gems},
+    models::{GemColor, GemDetailResponse, GemVariant, SkillGemResponse},
+    AppState,
+};
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GemDetailQuery {
+    /// League to fetch gem prices for, or `current` / `current-hc` for the active challenge league (default: `defaults.league`)
+    league: Option<String>,
+}
+
+/// Returns every variant of a single gem with prices, listing counts and 7-day sparklines.
+///
+/// For transfigured gems the response also names the base gem and the other
+/// transfigured gems of the same color.
+#[utoipa::path(
+    get,
+    path = "/api/v1/gems/{name}",
+    tag = "gems",
+    params(
+        ("name" = String, Path, description = "Gem name, matched case-insensitively"),
+        GemDetailQuery
+    ),
+    responses(
+        (status = 200, description = "All variants of the gem", body = GemDetailResponse),
+        (status = 404, description = "No gem with that name in the league, or no data for the league"),
+        (status = 429, description = "Too many requests from this client or to POE Ninja; see Retry-After"),
+        (status = 502, description = "POE Ninja returned an error")
+    )
+)]
+pub async fn get_gem_details(
+    Path(name): Path<String>,
+    Query(params): Query<GemDetailQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<GemDetailResponse>, StatusCode> {
+    let league = params.league.unwrap_or_else(|| state.config.defaults.league.clone());
+    let league = resolve_league(&state, &league).await;
+    let skill_gems_response = fetch_skill_gems(&state, &league).await?;
+
+    info!("Looking up gem details for '{}' in league: {}", name, league);
+
+    match build_gem_details(&name, &league, &skill_gems_response) {
+        Some(details) => Ok(Json(details)),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+fn build_gem_details(name: &str, league: &str, response: &SkillGemResponse) -> Option<GemDetailResponse> {
+    let lines: Vec<_> = response
+        .lines
+        .iter()
+        .filter(|gem| gem.name.eq_ignore_ascii_case(name))
+        .collect();
+
+    let canonical_name = lines.first()?.name.clone();
+    let names: HashSet<&str> = response.lines.iter().map(|gem| gem.name.as_str()).collect();
+    let base_gem = base_gem_of(&canonical_name, &names).map(str::to_string);
+    let transfigured = base_gem.is_some();
+    let color = lines
+        .iter()
+        .find_map(|gem| gem.icon.as_deref().and_then(GemColor::from_icon_url));
+
+    let mut variants: Vec<GemVariant> = lines
+        .iter()
+        .map(|gem| GemVariant {
+            gem_level: gem.gem_level.unwrap_or(1),
+            gem_quality: gem.gem_quality.unwrap_or(0),
+            corrupted: gem.corrupted.unwrap_or(false),
+            chaos_value: gem.chaos_value,
+            divine_value: gem.divine_value,
+            count: gem.count,
+            listing_count: gem.listing_count,
+            sparkline: gem.sparkline.clone(),
+            low_confidence_sparkline: gem.low_confidence_sparkline.clone(),
+        })
+        .collect();
+    variants.sort_by_key(|v| (v.gem_level, v.gem_quality, v.corrupted));
+
+    let mut color_pool: Vec<String> = match (transfigured, color) {
+        (true, Some(color)) => response
+            .lines
+            .iter()
+            .filter(|gem| gem.name != canonical_name && base_gem_of(&gem.name, &names).is_some())
+            .filter(|gem| gem.icon.as_deref().and_then(GemColor::from_icon_url) == Some(color))
+            .map(|gem| gem.name.clone())
+            .collect(),
+        _ => Vec::new(),
+    };
+    color_pool.sort();
+    color_pool.dedup();
+
+    Some(GemDetailResponse {
+        name: canonical_name,
+        league: league.to_string(),
+        color: color.map(|c| c.as_str().to_string()),
+        transfigured,
+        base_gem,
+        color_pool,
+        variants,
+    })
+}
+
+/// The base gem a transfigured gem is made from, None for gems that aren't transfigured.
+///
+/// Base gems can contain " of " themselves ("Rain of Arrows of Artillery"), so the
+/// longest prefix before an " of " that is a listed gem wins. A gem other listed gems
+/// are transfigured from ("Rain of Arrows") is a base gem whatever its name.
+fn base_gem_of<'a>(name: &'a str, names: &HashSet<&str>) -> Option<&'a str> {
+    let transfigured_prefix = format!("{} of ", name);
+    if names.iter().any(|other| other.starts_with(&transfigured_prefix)) {
+        return None;
+    }
+    name.rmatch_indices(" of ")
+        .map(|(idx, _)| &name[..idx])
+        .find(|prefix| names.contains(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED_ICON: &str = "https://web.poecdn.com/gen/image/WzMwLDE0LHsiZiI6IjJESXRlbXMvR2Vtcy9Nb2x0ZW5TdHJpa2UiLCJ3IjoxLCJoIjoxLCJzY2FsZSI6MSwiZ2QiOjV9XQ/b3e2ef9d6c/MoltenStrike.png";
+    const BLUE_ICON: &str = "https://web.poecdn.com/gen/image/WzMwLDE0LHsiZiI6IjJESXRlbXMvR2Vtcy9TcGFyayIsInciOjEsImgiOjEsInNjYWxlIjoxLCJnZCI6MTR9XQ/c9038eb883/Spark.png";
+
+    fn sample_response() -> SkillGemResponse {
+        serde_json::from_value(serde_json::json!({
+            "lines": [
+                { "name": "Rain of Arrows", "icon": RED_ICON, "chaosValue": 1.0 },
+                { "name": "Rain of Arrows of Artillery", "icon": RED_ICON, "gemLevel": 20, "gemQuality": 20, "chaosValue": 40.0 },
+                { "name": "Rain of Arrows of Artillery", "icon": RED_ICON, "chaosValue": 25.0, "listingCount": 12 },
+                { "name": "Molten Strike", "icon": RED_ICON, "chaosValue": 1.0 },
+                { "name": "Molten Strike of the Zenith", "icon": RED_ICON, "chaosValue": 10.0 },
+                { "name": "Spark", "icon": BLUE_ICON, "chaosValue": 1.0 },
+                { "name": "Spark of Unpredictability", "icon": BLUE_ICON, "chaosValue": 5.0 }
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_build_gem_details() {
+        let response = sample_response();
+        let details = build_gem_details("rain of arrows of artillery", "Standard", &response).unwrap();
+
+        assert_eq!(details.name, "Rain of Arrows of Artillery");
+        assert_eq!(details.base_gem.as_deref(), Some("Rain of Arrows"));
+        assert_eq!(details.color.as_deref(), Some("red"));
+        assert_eq!(details.color_pool, vec!["Molten Strike of the Zenith".to_string()]);
+        assert_eq!(details.variants.len(), 2);
+        assert_eq!(details.variants[0].gem_level, 1);
+        assert_eq!(details.variants[0].listing_count, Some(12));
+        assert_eq!(details.variants[1].gem_quality, 20);
+
+        // A base gem with " of " in its name isn't transfigured
+        let base = build_gem_details("Rain of Arrows", "Standard", &response).unwrap();
+        assert!(!base.transfigured);
+        assert_eq!(base.base_gem, None);
+        assert!(base.color_pool.is_empty());
+
+        assert!(build_gem_details("Nonexistent Gem", "Standard", &response).is_none());
+    }
+}
@@ -0,0 +1,94 @@
+use axum::{extract::State, http::StatusCode, response::Json};
+use chrono::Utc;
+
+use crate::{
+    models::{CacheStatus, HealthResponse, LeagueFreshness, ReadinessResponse, UpstreamReport},
+    upstream::Upstream,
+    AppState,
+};
+
+/// Liveness probe. Succeeds whenever the process can answer requests.
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    responses((status = 200, description = "Process is alive", body = HealthResponse))
+)]
+pub async fn health_check(State(state): State<AppState>) -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: "ok".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_seconds: state.started_at.elapsed().as_secs(),
+    })
+}
+
+/// Readiness probe.
+///
+/// Fails with 503 when the cache directory cannot be written. Otherwise reports
+/// "degraded" when an upstream's last request failed or a league's cached data has
+/// expired, so monitors can tell why an instance is serving stale results.
+#[utoipa::path(
+    get,
+    path = "/ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "Ready or degraded", body = ReadinessResponse),
+        (status = 503, description = "Cache directory is not writable", body = ReadinessResponse)
+    )
+)]
+pub async fn readiness_check(State(state): State<AppState>) -> (StatusCode, Json<ReadinessResponse>) {
+    let cache = match state.cache.check_writable().await {
+        Ok(()) => CacheStatus { writable: true, error: None },
+        Err(e) => CacheStatus { writable: false, error: Some(format!("{:#}", e)) },
+    };
+
+    let leagues: Vec<LeagueFreshness> = state
+        .cache
+        .entries()
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|entry| {
+            let league = entry.key.strip_prefix("skillGems_")?.to_string();
+            Some(LeagueFreshness {
+                league,
+                updated_at: entry.timestamp,
+                age_minutes: (Utc::now() - entry.timestamp).num_minutes(),
+                fresh: !entry.expired,
+            })
+        })
+        .collect();
+
+    let upstreams: Vec<UpstreamReport> = Upstream::ALL
+        .iter()
+        .map(|upstream| {
+            let status = state.upstreams.status(*upstream);
+            UpstreamReport {
+                name: upstream.as_str().to_string(),
+                healthy: status.is_healthy(),
+                last_success: status.last_success,
+                last_failure: status.last_failure,
+                last_error: status.last_error,
+            }
+        })
+        .collect();
+
+    let (code, status) = if !cache.writable {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    } else if upstreams.iter().any(|u| !u.healthy) || leagues.iter().any(|l| !l.fresh) {
+        (StatusCode::OK, "degraded")
+    } else {
+        (StatusCode::OK, "ready")
+    };
+
+    let response = ReadinessResponse {
+        status: status.to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_seconds: state.started_at.elapsed().as_secs(),
+        cache,
+        leagues,
+        upstreams,
+    };
+
+    (code, Json(response))
+}
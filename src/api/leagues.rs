@@ -1,132 +1,399 @@
-use axum::{extract::State, http::StatusCode, response::Json};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::time::Instant;
 use tracing::{error, info, warn};
+use utoipa::IntoParams;
 
 use crate::{
-    models::{League, LeaguesApiResponse, OfficialLeague},
+    api::skill_gems::fetch_skill_gems,
+    filter::LeagueFilter,
+    models::{League, LeaguesApiResponse, NinjaIndexState, NinjaLeague, OfficialLeague},
+    upstream::Upstream,
     AppState,
 };
 
+/// Realms accepted by the official leagues API.
+pub const REALMS: [&str; 3] = ["pc", "xbox", "sony"];
+
+/// League alias that resolves to the active softcore challenge league.
+pub const CURRENT_LEAGUE_ALIAS: &str = "current";
+
+/// League alias that resolves to the active hardcore challenge league.
+pub const CURRENT_HARDCORE_LEAGUE_ALIAS: &str = "current-hc";
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LeaguesQuery {
+    /// Realm to list leagues for: pc, xbox or sony (default: pc)
+    realm: Option<String>,
+}
+
 /// Fetches available leagues from the official Path of Exile API and caches them.
 /// 
 /// This endpoint dynamically retrieves the current leagues from GGG's official API instead of poe.ninja,
 /// which automatically includes new challenge leagues as they become available.
-/// Results are lazily cached for 1 hour to reduce API calls.
+/// Results are lazily cached (1 hour by default, see `cache.ttl_minutes`) to reduce API calls.
 /// 
-/// Only "main" leagues relevant for economy tracking are returned (excludes SSF, and old leagues).
+/// Only "main" leagues relevant for economy tracking are returned, as decided by the
+/// configured `LeagueFilter`. On pc, leagues poe.ninja has no economy data for are dropped too.
 /// 
 /// If the official API is unavailable, falls back to the permanent leagues
 /// (Standard and Hardcore) which always exist.
-pub async fn get_leagues(State(state): State<AppState>) -> Result<Json<LeaguesApiResponse>, StatusCode> {
-    let cache_key = "leagues";
-
-    // Try to get from cache first
-    if let Ok(Some(cached_leagues)) = state.cache.get::<LeaguesApiResponse>(cache_key).await {
-        info!("Returning cached leagues data");
-        return Ok(Json(cached_leagues));
+#[utoipa::path(
+    get,
+    path = "/api/v1/leagues",
+    tag = "leagues",
+    params(LeaguesQuery),
+    responses(
+        (status = 200, description = "Leagues with economy data", body = LeaguesApiResponse),
+        (status = 400, description = "Unknown realm"),
+        (status = 429, description = "Too many requests from this client; see Retry-After")
+    )
+)]
+pub async fn get_leagues(
+    Query(params): Query<LeaguesQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<LeaguesApiResponse>, StatusCode> {
+    let realm = params.realm.unwrap_or_else(|| "pc".to_string()).to_lowercase();
+    if !REALMS.contains(&realm.as_str()) {
+        return Err(StatusCode::BAD_REQUEST);
     }
 
-    // Fetch fresh data from official PoE API
-    info!("Fetching fresh leagues data from official PoE API");
-
-    let url = "https://api.pathofexile.com/leagues?type=main&realm=pc";
-    let response = match state.client.get(url).send().await {
-        Ok(response) => response,
-        Err(e) => {
-            error!("Failed to fetch leagues from PoE API: {}", e);
-            info!("Using fallback leagues due to network error");
-            return Ok(Json(get_fallback_leagues()));
+    Ok(Json(fetch_leagues(&state, &realm).await))
+}
+
+/// Loads the economy leagues of a realm, followed by the configured private leagues
+/// poe.ninja has data for.
+pub async fn fetch_leagues(state: &AppState, realm: &str) -> LeaguesApiResponse {
+    let mut response = fetch_official_leagues(state, realm).await;
+
+    // Private leagues are only configured for pc, where poe.ninja tracks them
+    if realm == "pc" && !state.config.leagues.private.is_empty() {
+        let index = fetch_ninja_index(state).await;
+        for id in state.config.leagues.private.iter() {
+            if response.leagues.iter().any(|league| &league.name == id) {
+                continue;
+            }
+            if check_league_availability(state, id).await {
+                let hardcore = index.as_ref().and_then(|index| ninja_hardcore(index, id));
+                response.leagues.push(private_league(id, hardcore.unwrap_or(false)));
+            }
         }
-    };
+    }
 
-    // Check response status
-    if !response.status().is_success() {
-        warn!("PoE API returned non-success status: {}", response.status());
-        info!("Using fallback leagues due to API error");
-        return Ok(Json(get_fallback_leagues()));
+    response
+}
+
+/// Checks whether poe.ninja has skill gem prices for a league, caching the answer
+/// for `cache.ttl_minutes`. Used for private leagues, which the official API never lists.
+///
+/// Only a definite answer is cached: prices, or poe.ninja having none. A failed
+/// check (rate limited, upstream error, timeout) counts as unavailable this time and
+/// is retried on the next request.
+pub async fn check_league_availability(state: &AppState, league: &str) -> bool {
+    let cache_key = format!("leagueAvailability_{}", league);
+
+    if let Ok(Some(available)) = state.cache.get::<bool>(&cache_key).await {
+        return available;
     }
 
-    // Get response body as text first for better error diagnostics
-    let body_text = match response.text().await {
-        Ok(text) => text,
-        Err(e) => {
-            error!("Failed to read response body: {}", e);
-            info!("Using fallback leagues");
-            return Ok(Json(get_fallback_leagues()));
+    let available = match fetch_skill_gems(state, league).await {
+        Ok(gems) => !gems.lines.is_empty(),
+        Err(StatusCode::NOT_FOUND) => false,
+        Err(status) => {
+            warn!("Could not check poe.ninja data for league {}: {}", league, status);
+            return false;
         }
     };
 
-    // Check for empty response
-    if body_text.is_empty() {
-        warn!("PoE API returned empty response body");
-        info!("Using fallback leagues");
-        return Ok(Json(get_fallback_leagues()));
+    if let Err(e) = state.cache.set(&cache_key, available, state.config.cache.ttl_minutes).await {
+        warn!("Failed to cache availability of league {}: {}", league, e);
     }
 
-    // Parse the JSON - official API returns an array of leagues
-    let official_leagues: Vec<OfficialLeague> = match serde_json::from_str(&body_text) {
-        Ok(data) => data,
-        Err(e) => {
-            error!("Failed to parse leagues response: {}. Body preview: {}", e, &body_text.chars().take(200).collect::<String>());
-            info!("Using fallback leagues due to parse error");
-            return Ok(Json(get_fallback_leagues()));
+    info!("League {} has poe.ninja economy data: {}", league, available);
+    available
+}
+
+/// Whether poe.ninja's index lists a league as hardcore; None when it doesn't say.
+fn ninja_hardcore(index: &NinjaIndexState, name: &str) -> Option<bool> {
+    index
+        .economy_leagues
+        .iter()
+        .chain(&index.old_economy_leagues)
+        .find(|ninja| ninja.name.eq_ignore_ascii_case(name))?
+        .hardcore
+}
+
+/// A configured private league. The official API doesn't list private leagues, so
+/// whether it is hardcore comes from poe.ninja's index, if at all.
+fn private_league(id: &str, hardcore: bool) -> League {
+    League {
+        name: id.to_string(),
+        display_name: Some(id.to_string()),
+        hardcore,
+        indexed: true,
+        challenge: false,
+        private: true,
+        realm: Some("pc".to_string()),
+        start_at: None,
+        end_at: None,
+    }
+}
+
+/// Loads the economy leagues from the official API, serving them from the cache when
+/// possible.
+///
+/// Falls back to Standard and Hardcore when the official API can't be used.
+async fn fetch_official_leagues(state: &AppState, realm: &str) -> LeaguesApiResponse {
+    let cache_key = format!("leagues_{}", realm);
+
+    // Try to get from cache first
+    if let Ok(Some(cached_leagues)) = state.cache.get::<LeaguesApiResponse>(&cache_key).await {
+        info!("Returning cached leagues data for realm: {}", realm);
+        return cached_leagues;
+    }
+
+    if state.rate_limits.upstream.check(&()).is_err() {
+        warn!("Upstream request budget used up, using fallback leagues for realm: {}", realm);
+        state.metrics.observe_rate_limited("upstream");
+        return get_fallback_leagues(realm);
+    }
+
+    // Fetch fresh data from official PoE API
+    info!("Fetching fresh leagues data from official PoE API for realm: {}", realm);
+
+    let started = Instant::now();
+    let result = request_official_leagues(state, realm).await;
+    state
+        .metrics
+        .observe_upstream(Upstream::PoeApi, started.elapsed(), result.is_ok());
+
+    let official_leagues = match result {
+        Ok(leagues) => leagues,
+        Err(message) => {
+            error!("{}", message);
+            info!("Using fallback leagues");
+            state.upstreams.record_failure(Upstream::PoeApi, message);
+            return get_fallback_leagues(realm);
         }
     };
 
+    state.upstreams.record_success(Upstream::PoeApi);
+
     // Convert official leagues to our format, filtering for economy-relevant leagues
     // We exclude SSF, Ruthless, and other variants that POE Ninja doesn't track
-    let leagues: Vec<League> = official_leagues
+    let mut leagues: Vec<League> = official_leagues
         .into_iter()
-        .filter(|league| is_economy_league(league))
-        .map(|league| {
-            let is_hardcore = league.rules
-                .as_ref()
-                .map(|rules| rules.iter().any(|r| r.id == "Hardcore"))
-                .unwrap_or(false);
-            
-            League {
-                name: league.id.clone(),
-                display_name: Some(league.id),
-                hardcore: is_hardcore,
-                indexed: true,
-            }
-        })
+        .filter(|league| state.league_filter.is_economy_league(league))
+        .map(|league| to_league(league, realm))
         .collect();
 
+    // poe.ninja only tracks pc economies
+    if realm == "pc" {
+        if let Some(index) = fetch_ninja_index(state).await {
+            apply_ninja_index(&mut leagues, &index, &state.league_filter);
+        }
+    }
+
     let api_response = LeaguesApiResponse { leagues };
 
-    // Cache the response for 1 hour
-    if let Err(e) = state.cache.set(cache_key, &api_response, 60).await {
+    // Cache the response
+    if let Err(e) = state.cache.set(&cache_key, &api_response, state.config.cache.ttl_minutes).await {
         error!("Failed to cache leagues data: {}", e);
         // Continue anyway, don't fail the request, since we will still have the cached data.
     }
 
     info!("Successfully fetched and cached {} leagues", api_response.leagues.len());
-    Ok(Json(api_response))
+    api_response
+}
+
+/// Resolves the `current` and `current-hc` aliases to the active challenge league.
+///
+/// Any other league name is returned unchanged. When no challenge league is running
+/// the aliases fall back to Standard and Hardcore.
+pub async fn resolve_league(state: &AppState, league: &str) -> String {
+    let hardcore = if league.eq_ignore_ascii_case(CURRENT_LEAGUE_ALIAS) {
+        false
+    } else if league.eq_ignore_ascii_case(CURRENT_HARDCORE_LEAGUE_ALIAS) {
+        true
+    } else {
+        return league.to_string();
+    };
+
+    let leagues = fetch_leagues(state, "pc").await;
+    let resolved = match select_current_league(&leagues.leagues, hardcore, Utc::now()) {
+        Some(current) => current.name.clone(),
+        None if hardcore => "Hardcore".to_string(),
+        None => "Standard".to_string(),
+    };
+
+    info!("Resolved league alias '{}' to '{}'", league, resolved);
+    resolved
 }
 
-/// Determines if a league is relevant for economy tracking on POE Ninja.
-/// Excludes SSF, Ruthless, and other variants that don't have economy data.
-fn is_economy_league(league: &OfficialLeague) -> bool {
-    let id = &league.id;
-    
-    // Exclude SSF leagues
-    if id.contains("SSF") || id.contains("Solo Self-Found") {
-        return false;
+/// Picks the most recently started challenge league that hasn't ended yet.
+fn select_current_league(leagues: &[League], hardcore: bool, now: DateTime<Utc>) -> Option<&League> {
+    leagues
+        .iter()
+        .filter(|league| league.challenge && league.hardcore == hardcore)
+        .filter(|league| league.start_at.is_none_or(|start| start <= now))
+        .filter(|league| league.end_at.is_none_or(|end| end > now))
+        .max_by_key(|league| league.start_at)
+}
+
+/// Requests the league list from the official API, returning a log message on failure.
+async fn request_official_leagues(state: &AppState, realm: &str) -> Result<Vec<OfficialLeague>, String> {
+    let url = format!("{}/leagues?type=main&realm={}", state.config.upstream.poe_api_url, realm);
+    let response = state
+        .client
+        .get(&url)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch leagues from PoE API: {}", e))?;
+
+    // Check response status
+    if !response.status().is_success() {
+        return Err(format!("PoE API returned non-success status: {}", response.status()));
     }
-    
-    // Exclude Ruthless leagues
-    if id.contains("Ruthless") {
-        return false;
+
+    // Get response body as text first for better error diagnostics
+    let body_text = response
+        .text()
+        .await
+        .map_err(|e| format!("Failed to read response body: {}", e))?;
+
+    // Check for empty response
+    if body_text.is_empty() {
+        return Err("PoE API returned empty response body".to_string());
+    }
+
+    // Parse the JSON - official API returns an array of leagues
+    serde_json::from_str(&body_text).map_err(|e| {
+        format!(
+            "Failed to parse leagues response: {}. Body preview: {}",
+            e,
+            &body_text.chars().take(200).collect::<String>()
+        )
+    })
+}
+
+fn to_league(league: OfficialLeague, realm: &str) -> League {
+    let is_hardcore = league.rules
+        .as_ref()
+        .map(|rules| rules.iter().any(|r| r.id == "Hardcore"))
+        .unwrap_or(false);
+
+    // Standard and Hardcore are the only permanent trade leagues; events, when
+    // offered at all, never stand in for the current challenge league
+    let is_challenge = league.id != "Standard" && league.id != "Hardcore" && !league.event.unwrap_or(false);
+
+    League {
+        display_name: Some(league.id.clone()),
+        name: league.id,
+        hardcore: is_hardcore,
+        indexed: true,
+        challenge: is_challenge,
+        private: false,
+        realm: Some(league.realm.unwrap_or_else(|| realm.to_string())),
+        start_at: parse_timestamp(league.start_at.as_deref()),
+        end_at: parse_timestamp(league.end_at.as_deref()),
+    }
+}
+
+fn parse_timestamp(value: Option<&str>) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value?)
+        .ok()
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+}
+
+/// Loads poe.ninja's league index, cached for `cache.ttl_minutes`. Returns `None` if it can't be
+/// fetched, in which case leagues are offered without checking it.
+async fn fetch_ninja_index(state: &AppState) -> Option<NinjaIndexState> {
+    let cache_key = "ninjaIndex";
+
+    if let Ok(Some(cached)) = state.cache.get::<NinjaIndexState>(cache_key).await {
+        return Some(cached);
     }
-    
-    // Include Standard, Hardcore, and challenge leagues
-    true
+
+    if state.rate_limits.upstream.check(&()).is_err() {
+        warn!("Upstream request budget used up; offering leagues without checking poe.ninja");
+        state.metrics.observe_rate_limited("upstream");
+        return None;
+    }
+
+    let started = Instant::now();
+    let result = request_ninja_index(state).await;
+    state
+        .metrics
+        .observe_upstream(Upstream::PoeNinja, started.elapsed(), result.is_ok());
+
+    let index = match result {
+        Ok(index) => index,
+        Err(message) => {
+            warn!("{}; offering leagues without checking poe.ninja", message);
+            state.upstreams.record_failure(Upstream::PoeNinja, message);
+            return None;
+        }
+    };
+    state.upstreams.record_success(Upstream::PoeNinja);
+
+    if let Err(e) = state.cache.set(cache_key, &index, state.config.cache.ttl_minutes).await {
+        warn!("Failed to cache poe.ninja index state: {}", e);
+    }
+
+    Some(index)
+}
+
+async fn request_ninja_index(state: &AppState) -> Result<NinjaIndexState, String> {
+    let response = state
+        .client
+        .get(format!("{}/api/data/getindexstate", state.config.upstream.poe_ninja_url))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch poe.ninja index state: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!("poe.ninja index state returned status: {}", response.status()));
+    }
+
+    response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse poe.ninja index state: {}", e))
+}
+
+/// Drops leagues poe.ninja has no economy data for and marks leagues it only keeps
+/// as old economies as not indexed. Allow-listed leagues are always kept.
+fn apply_ninja_index(leagues: &mut Vec<League>, index: &NinjaIndexState, filter: &LeagueFilter) {
+    let listed = |ninja_leagues: &[NinjaLeague], name: &str| {
+        ninja_leagues.iter().any(|ninja| ninja.name.eq_ignore_ascii_case(name))
+    };
+
+    leagues.retain_mut(|league| {
+        if listed(&index.economy_leagues, &league.name) {
+            league.indexed = true;
+            true
+        } else if listed(&index.old_economy_leagues, &league.name) {
+            league.indexed = false;
+            true
+        } else if filter.is_allowlisted(&league.name) {
+            true
+        } else {
+            info!("Skipping league without poe.ninja economy data: {}", league.name);
+            false
+        }
+    });
 }
 
 /// Fallback leagues used only when the official PoE API is unavailable.
 /// The actual leagues are dynamically fetched from https://api.pathofexile.com/leagues
-/// and cached for 1 hour. This fallback provides the permanent leagues that always exist.
-fn get_fallback_leagues() -> LeaguesApiResponse {
+/// and cached. This fallback provides the permanent leagues that always exist.
+fn get_fallback_leagues(realm: &str) -> LeaguesApiResponse {
     LeaguesApiResponse {
         leagues: vec![
             League {
@@ -134,13 +401,144 @@ fn get_fallback_leagues() -> LeaguesApiResponse {
                 display_name: Some("Standard".to_string()),
                 hardcore: false,
                 indexed: true,
+                challenge: false,
+                private: false,
+                realm: Some(realm.to_string()),
+                start_at: None,
+                end_at: None,
             },
             League {
                 name: "Hardcore".to_string(),
                 display_name: Some("Hardcore".to_string()),
                 hardcore: true,
                 indexed: true,
+                challenge: false,
+                private: false,
+                realm: Some(realm.to_string()),
+                start_at: None,
+                end_at: None,
             },
         ],
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn official(value: serde_json::Value) -> OfficialLeague {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_to_league_metadata() {
+        let league = to_league(
+            official(serde_json::json!({
+                "id": "Hardcore Settlers",
+                "realm": "pc",
+                "startAt": "2024-07-26T19:00:00Z",
+                "endAt": null,
+                "rules": [{ "id": "Hardcore", "name": "Hardcore" }]
+            })),
+            "pc",
+        );
+        assert!(league.hardcore);
+        assert!(league.challenge);
+        assert_eq!(league.realm.as_deref(), Some("pc"));
+        assert_eq!(league.start_at.unwrap().to_rfc3339(), "2024-07-26T19:00:00+00:00");
+        assert!(league.end_at.is_none());
+
+        let standard = to_league(official(serde_json::json!({ "id": "Standard" })), "xbox");
+        assert!(!standard.challenge);
+        assert!(!standard.hardcore);
+        assert_eq!(standard.realm.as_deref(), Some("xbox"));
+
+        let event = to_league(official(serde_json::json!({ "id": "Solo Event", "event": true })), "pc");
+        assert!(!event.challenge);
+    }
+
+    #[test]
+    fn test_private_league() {
+        let league = private_league("Guild League (PL12345)", false);
+        assert!(league.private);
+        assert!(!league.challenge);
+        assert!(!league.hardcore);
+
+        // Names say nothing about hardcore; only poe.ninja's index does
+        let index: NinjaIndexState = serde_json::from_value(serde_json::json!({
+            "economyLeagues": [
+                { "name": "Guild HC League (PL12346)", "hardcore": false },
+                { "name": "Guild Deaths (PL12347)", "hardcore": true }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(ninja_hardcore(&index, "Guild HC League (PL12346)"), Some(false));
+        assert_eq!(ninja_hardcore(&index, "guild deaths (pl12347)"), Some(true));
+        assert_eq!(ninja_hardcore(&index, "Unknown (PL1)"), None);
+    }
+
+    #[test]
+    fn test_apply_ninja_index() {
+        let mut leagues: Vec<League> = ["Standard", "Settlers", "Necropolis", "Brand New"]
+            .into_iter()
+            .map(|id| to_league(official(serde_json::json!({ "id": id })), "pc"))
+            .collect();
+        let index: NinjaIndexState = serde_json::from_value(serde_json::json!({
+            "economyLeagues": [{ "name": "Standard" }, { "name": "Settlers" }],
+            "oldEconomyLeagues": [{ "name": "Necropolis" }]
+        }))
+        .unwrap();
+
+        apply_ninja_index(&mut leagues, &index, &LeagueFilter::default());
+
+        let names: Vec<_> = leagues.iter().map(|l| (l.name.as_str(), l.indexed)).collect();
+        assert_eq!(names, vec![("Standard", true), ("Settlers", true), ("Necropolis", false)]);
+    }
+
+    #[test]
+    fn test_select_current_league() {
+        let leagues: Vec<League> = [
+            serde_json::json!({ "id": "Standard" }),
+            serde_json::json!({ "id": "Hardcore", "rules": [{ "id": "Hardcore" }] }),
+            serde_json::json!({ "id": "Necropolis", "startAt": "2024-03-29T19:00:00Z", "endAt": "2024-07-22T21:00:00Z" }),
+            serde_json::json!({ "id": "Settlers", "startAt": "2024-07-26T19:00:00Z" }),
+            serde_json::json!({ "id": "Solo Event", "startAt": "2024-07-30T19:00:00Z", "event": true }),
+            serde_json::json!({ "id": "Hardcore Settlers", "startAt": "2024-07-26T19:00:00Z", "rules": [{ "id": "Hardcore" }] }),
+        ]
+        .into_iter()
+        .map(|value| to_league(official(value), "pc"))
+        .collect();
+
+        let now = DateTime::parse_from_rfc3339("2024-08-01T00:00:00Z").unwrap().with_timezone(&Utc);
+        assert_eq!(select_current_league(&leagues, false, now).unwrap().name, "Settlers");
+        assert_eq!(select_current_league(&leagues, true, now).unwrap().name, "Hardcore Settlers");
+
+        let before_settlers = DateTime::parse_from_rfc3339("2024-05-01T00:00:00Z").unwrap().with_timezone(&Utc);
+        assert_eq!(select_current_league(&leagues, false, before_settlers).unwrap().name, "Necropolis");
+        assert!(select_current_league(&leagues, true, before_settlers).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_league_availability_caches_definite_answers() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = crate::config::Config::default();
+        config.cache.dir = temp_dir.path().to_str().unwrap().to_string();
+        config.rate_limit.upstream_requests_per_minute = 1;
+        let state = AppState::from_config(config).unwrap();
+        state.rate_limits.upstream.check(&()).unwrap();
+
+        // The upstream budget is used up: unavailable for now, checked again next time
+        assert!(!check_league_availability(&state, "Guild (PL1)").await);
+        assert_eq!(state.cache.get::<bool>("leagueAvailability_Guild (PL1)").await.unwrap(), None);
+
+        // poe.ninja not knowing the league is an answer
+        state.cache.set("unknownLeague_Guild (PL1)", true, 10).await.unwrap();
+        assert!(!check_league_availability(&state, "Guild (PL1)").await);
+        assert_eq!(state.cache.get::<bool>("leagueAvailability_Guild (PL1)").await.unwrap(), Some(false));
+
+        let gems = serde_json::json!({ "lines": [{ "name": "Spark of Unpredictability", "chaosValue": 5.0 }] });
+        state.cache.set("skillGems_Party (PL2)", gems, 60).await.unwrap();
+        assert!(check_league_availability(&state, "Party (PL2)").await);
+        assert_eq!(state.cache.get::<bool>("leagueAvailability_Party (PL2)").await.unwrap(), Some(true));
+    }
+}
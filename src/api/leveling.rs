@@ -0,0 +1,97 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+use tracing::info;
+use utoipa::IntoParams;
+
+use crate::{
+    api::{currency::input_price, leagues::resolve_league, skill_gems::fetch_skill_gems},
+    engine::leveling::{leveling_plans, LevelingOptions, LevelingSort},
+    models::LevelingResponse,
+    AppState,
+};
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LevelingQuery {
+    /// League to fetch gem prices for, or `current` / `current-hc` for the active challenge league (default: `defaults.league`)
+    league: Option<String>,
+    /// Gemcutter's Prism price in chaos (default: the poe.ninja price, or `defaults.gemcutter_chaos` without one)
+    gemcutter_chaos: Option<f64>,
+    /// Time or opportunity cost of gaining one gem level in chaos (default: `defaults.level_cost_chaos`)
+    level_cost_chaos: Option<f64>,
+    /// Ranking order: profit, roi or listing_count (default: profit)
+    sort: Option<LevelingSort>,
+    /// Only include gems of this color: red, green or blue
+    color: Option<String>,
+    /// Only include transfigured gems (default: false)
+    transfigured_only: Option<bool>,
+    /// Only include plans that make a profit (default: false)
+    profitable_only: Option<bool>,
+}
+
+/// Ranks leveling 1/0 gems to 20/0 and raising 20/0 gems to 20/20 with Gemcutter's Prisms.
+///
+/// Each plan buys the gem at one variant and sells it at another. Its inputs are one
+/// Gemcutter's Prism per quality point plus `level_cost_chaos` per gem level gained.
+#[utoipa::path(
+    get,
+    path = "/api/v1/leveling",
+    tag = "calculation",
+    params(LevelingQuery),
+    responses(
+        (status = 200, description = "Leveling and quality plans, best first", body = LevelingResponse),
+        (status = 400, description = "Negative input price"),
+        (status = 404, description = "POE Ninja has no data for the league"),
+        (status = 429, description = "Too many requests from this client or to POE Ninja; see Retry-After"),
+        (status = 502, description = "POE Ninja returned an error"),
+        (status = 500, description = "POE Ninja could not be reached or parsed")
+    )
+)]
+pub async fn get_leveling_plans(
+    Query(params): Query<LevelingQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<LevelingResponse>, StatusCode> {
+    let league = params.league.unwrap_or_else(|| state.config.defaults.league.clone());
+    let league = resolve_league(&state, &league).await;
+    let skill_gems_response = fetch_skill_gems(&state, &league).await?;
+    let gemcutter_chaos = match params.gemcutter_chaos {
+        Some(price) => price,
+        None => input_price(&state, &league, "Gemcutter's Prism", state.config.defaults.gemcutter_chaos).await,
+    };
+    let options = LevelingOptions {
+        gemcutter_chaos,
+        level_cost_chaos: params.level_cost_chaos.unwrap_or(state.config.defaults.level_cost_chaos),
+        transfigured_only: params.transfigured_only.unwrap_or(false),
+    };
+    if options.gemcutter_chaos.is_nan()
+        || options.gemcutter_chaos < 0.0
+        || options.level_cost_chaos.is_nan()
+        || options.level_cost_chaos < 0.0
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    info!(
+        "Planning gem leveling for league: {}, gemcutter: {}c, per level: {}c",
+        league, options.gemcutter_chaos, options.level_cost_chaos
+    );
+
+    let mut plans = leveling_plans(&skill_gems_response, &options, params.sort.unwrap_or_default());
+    if let Some(color) = &params.color {
+        plans.retain(|plan| plan.color.as_deref().is_some_and(|plan_color| plan_color.eq_ignore_ascii_case(color)));
+    }
+    if params.profitable_only.unwrap_or(false) {
+        plans.retain(|plan| plan.profit > 0.0);
+    }
+
+    Ok(Json(LevelingResponse {
+        league,
+        gemcutter_chaos: options.gemcutter_chaos,
+        level_cost_chaos: options.level_cost_chaos,
+        plans,
+    }))
+}
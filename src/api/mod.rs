@@ -1,5 +1,122 @@
+pub mod alerts;
+pub mod corruption;
+pub mod currency;
+pub mod gems;
+pub mod health;
 pub mod leagues;
+pub mod leveling;
+pub mod results;
 pub mod skill_gems;
+pub mod stream;
 
+use utoipa::OpenApi;
+
+pub use alerts::{
+    create_alert_rule, delete_alert_rule, get_alert_rule, list_alert_deliveries, list_alert_rules, test_alert_rule,
+};
+pub use corruption::get_corruption_values;
+pub use currency::get_currency;
+pub use gems::get_gem_details;
+pub use health::{health_check, readiness_check};
 pub use leagues::get_leagues;
-pub use skill_gems::{calculate_gem_roi, get_skill_gems};
+pub use leveling::get_leveling_plans;
+pub use results::{get_result, save_result};
+pub use skill_gems::{calculate_gem_roi, get_calculation_defaults, get_skill_gems};
+pub use stream::stream_calculation;
+
+use results::SavedCalculation;
+use skill_gems::{CalculationDefaults, CalculationQuery};
+use crate::alerts::{AlertComparison, AlertDelivery, AlertMetric, AlertPayload, AlertRule, NewAlertRule};
+use crate::export::ExportFormat;
+use crate::engine::{leveling::LevelingSort, outliers::OutlierPolicy};
+use crate::models::{
+    CacheStatus, CalculationResponse, CalculationUpdate, CorruptionOutcomeValue, CorruptionResponse,
+    ConvertedValues, CorruptionValue, CurrencyDetail, CurrencyLine, CurrencyOverviewResponse, ExplicitModifier, GemDetailResponse, GemValue,
+    DecliningGem, GemVariant, HealthResponse, OutlierAdjustment, OverriddenGem, ImplicitModifier, League, LeagueFreshness, LeaguesApiResponse,
+    LevelingPlan, LevelingResponse, ProjectedValues, ReadinessResponse, SkillGem, SkillGemResponse, Sparkline,
+    UpstreamReport,
+};
+
+/// OpenAPI description of the versioned `/api/v1` routes, generated from the handler
+/// annotations and the types in `models`.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "POE Gem Calculator API",
+        description = "Transfigured skill gem prices and expected values from poe.ninja"
+    ),
+    paths(
+        leagues::get_leagues,
+        skill_gems::get_skill_gems,
+        currency::get_currency,
+        skill_gems::calculate_gem_roi,
+        skill_gems::get_calculation_defaults,
+        stream::stream_calculation,
+        corruption::get_corruption_values,
+        leveling::get_leveling_plans,
+        results::save_result,
+        results::get_result,
+        alerts::list_alert_rules,
+        alerts::create_alert_rule,
+        alerts::get_alert_rule,
+        alerts::delete_alert_rule,
+        alerts::test_alert_rule,
+        alerts::list_alert_deliveries,
+        gems::get_gem_details,
+        health::health_check,
+        health::readiness_check,
+    ),
+    components(schemas(
+        League,
+        LeaguesApiResponse,
+        SkillGemResponse,
+        SkillGem,
+        Sparkline,
+        ImplicitModifier,
+        ExplicitModifier,
+        CurrencyDetail,
+        CurrencyOverviewResponse,
+        CurrencyLine,
+        CalculationResponse,
+        CalculationUpdate,
+        ConvertedValues,
+        GemValue,
+        OverriddenGem,
+        OutlierAdjustment,
+        ProjectedValues,
+        DecliningGem,
+        OutlierPolicy,
+        ExportFormat,
+        CorruptionResponse,
+        CorruptionValue,
+        CorruptionOutcomeValue,
+        LevelingResponse,
+        LevelingPlan,
+        LevelingSort,
+        SavedCalculation,
+        CalculationQuery,
+        CalculationDefaults,
+        NewAlertRule,
+        AlertRule,
+        AlertComparison,
+        AlertMetric,
+        AlertPayload,
+        AlertDelivery,
+        GemDetailResponse,
+        GemVariant,
+        HealthResponse,
+        ReadinessResponse,
+        CacheStatus,
+        LeagueFreshness,
+        UpstreamReport,
+    )),
+    tags(
+        (name = "leagues", description = "Leagues with economy data"),
+        (name = "gems", description = "Raw and per-gem price data"),
+        (name = "calculation", description = "Transfigure expected value calculation"),
+        (name = "results", description = "Saved calculations behind shareable permalinks"),
+        (name = "alerts", description = "Alert rules on expected values and prices, delivered to webhooks"),
+        (name = "health", description = "Liveness and readiness probes")
+    )
+)]
+pub struct ApiDoc;
@@ -0,0 +1,181 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json},
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+use tracing::{error, info};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::{
+    api::skill_gems::{run_calculation, CalculationQuery},
+    models::{CalculationResponse, SkillGemResponse},
+    AppState,
+};
+
+/// Longest `results.retention_days` the config accepts.
+pub const MAX_RETENTION_DAYS: i64 = 3650;
+
+/// A price snapshot shared by saved calculations is rewritten, extending its expiry,
+/// at most this often.
+const SNAPSHOT_REFRESH_MINUTES: i64 = 60;
+
+/// A calculation saved under a permalink, with the prices it was run on.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SavedCalculation {
+    pub id: String,
+    pub league: String,
+    pub created_at: DateTime<Utc>,
+    /// When the saved calculation is deleted, after `results.retention_days`
+    pub expires_at: DateTime<Utc>,
+    /// When the price snapshot was fetched from POE Ninja
+    pub prices_updated_at: DateTime<Utc>,
+    /// Page of the web frontend that shows the saved calculation
+    pub permalink: String,
+    /// The query the calculation was run with, with the league resolved and every default filled in
+    pub inputs: CalculationQuery,
+    pub result: CalculationResponse,
+    /// The POE Ninja skill gem overview the calculation was run on. Stored once per
+    /// league and price update, shared by every calculation run on it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<SkillGemResponse>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SavedCalculationQuery {
+    /// Include the POE Ninja price snapshot the calculation was run on (default: false)
+    include_snapshot: Option<bool>,
+}
+
+/// Runs a calculation and saves it, with its price snapshot, under a new permalink.
+///
+/// Takes the same parameters as `/api/v1/calculate`. Saved calculations are kept for
+/// `results.retention_days` and never recalculated, so the permalink keeps showing
+/// the prices of the moment it was saved.
+#[utoipa::path(
+    post,
+    path = "/api/v1/results",
+    tag = "results",
+    params(CalculationQuery),
+    responses(
+        (status = 201, description = "Saved calculation, without its price snapshot; Location has its API URL", body = SavedCalculation),
+        (status = 400, description = "Unknown currency or malformed prices"),
+        (status = 404, description = "POE Ninja has no data for the league, or no price for the currency"),
+        (status = 429, description = "Too many requests from this client or to POE Ninja; see Retry-After"),
+        (status = 502, description = "POE Ninja returned an error"),
+        (status = 500, description = "POE Ninja could not be reached or parsed, or the calculation could not be saved")
+    )
+)]
+pub async fn save_result(
+    Query(params): Query<CalculationQuery>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let calculation = run_calculation(&state, params).await?;
+
+    let id = Uuid::new_v4().to_string();
+    // Config validation keeps the retention far from overflowing
+    let retention = Duration::try_days(state.config.results.retention_days).ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let created_at = Utc::now();
+    let expires_at = created_at.checked_add_signed(retention).ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let saved = SavedCalculation {
+        id: id.clone(),
+        league: calculation.league,
+        created_at,
+        expires_at,
+        prices_updated_at: calculation.updated_at,
+        permalink: format!("/results/{}", id),
+        inputs: calculation.inputs,
+        result: calculation.response,
+        snapshot: None,
+    };
+
+    if let Err(e) = save_snapshot(&state, &saved, &calculation.snapshot, retention).await {
+        error!("Failed to save price snapshot for calculation {}: {}", id, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    if let Err(e) = state.results.set(&result_key(&id), &saved, retention.num_minutes()).await {
+        error!("Failed to save calculation {}: {}", id, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    info!("Saved calculation {} for league: {}", id, saved.league);
+
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, format!("/api/v1/results/{}", id))],
+        Json(saved),
+    ))
+}
+
+/// Returns a saved calculation exactly as it was when it was saved.
+#[utoipa::path(
+    get,
+    path = "/api/v1/results/{id}",
+    tag = "results",
+    params(
+        ("id" = String, Path, description = "Id of the saved calculation"),
+        SavedCalculationQuery
+    ),
+    responses(
+        (status = 200, description = "Saved calculation", body = SavedCalculation),
+        (status = 404, description = "No saved calculation with this id, or it has expired"),
+        (status = 500, description = "The saved calculation could not be read")
+    )
+)]
+pub async fn get_result(
+    Path(id): Path<String>,
+    Query(params): Query<SavedCalculationQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<SavedCalculation>, StatusCode> {
+    // Only ids this server could have handed out name a file
+    let id = Uuid::parse_str(&id).map_err(|_| StatusCode::NOT_FOUND)?.to_string();
+
+    let mut saved = match state.results.get::<SavedCalculation>(&result_key(&id)).await {
+        Ok(Some(saved)) => saved,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to read saved calculation {}: {}", id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    if params.include_snapshot.unwrap_or(false) {
+        let key = snapshot_key(&saved.league, saved.prices_updated_at);
+        saved.snapshot = state.results.get(&key).await.map_err(|e| {
+            error!("Failed to read price snapshot of saved calculation {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    }
+
+    Ok(Json(saved))
+}
+
+/// Stores the price snapshot `saved` was run on, unless it is already stored.
+///
+/// The snapshot is kept `SNAPSHOT_REFRESH_MINUTES` longer than the retention and
+/// rewritten once it is older than that, so it outlives every calculation saved on it.
+async fn save_snapshot(
+    state: &AppState,
+    saved: &SavedCalculation,
+    snapshot: &SkillGemResponse,
+    retention: Duration,
+) -> anyhow::Result<()> {
+    let key = snapshot_key(&saved.league, saved.prices_updated_at);
+    let refresh = Duration::minutes(SNAPSHOT_REFRESH_MINUTES);
+    if let Some((_, written)) = state.results.get_with_timestamp::<IgnoredAny>(&key).await? {
+        if saved.created_at - written < refresh {
+            return Ok(());
+        }
+    }
+    state.results.set(&key, snapshot, (retention + refresh).num_minutes()).await?;
+    Ok(())
+}
+
+fn result_key(id: &str) -> String {
+    format!("result_{}", id)
+}
+
+fn snapshot_key(league: &str, updated_at: DateTime<Utc>) -> String {
+    format!("snapshot_{}_{}", league, updated_at.timestamp_millis())
+}
@@ -1,257 +1,494 @@
-use axum::{extract::{Query, State}, http::StatusCode, response::Json};
-use serde::Deserialize;
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
+use chrono::{DateTime, Utc};
+use std::time::Instant;
 use tracing::{error, info, warn};
 
 use crate::{
-    models::{SkillGemResponse, GemColor, CalculationResponse, GemValue, is_transfigured_gem},
+    api::{currency::conversion_rate, leagues::resolve_league, stream::SnapshotUpdate},
+    export::{render_calculation, render_skill_gems, ExportFormat, PriceSnapshot},
+    engine::{
+        calculate_with_options,
+        outliers::{OutlierOptions, OutlierPolicy},
+        trend::{TrendOptions, MAX_PROJECTION_HOURS},
+        parse_price_override,
+        CalculationOptions,
+        DEFAULT_OFFER_SIZE,
+    },
+    models::{SkillGemResponse, GemColor, CalculationResponse},
+    links::add_links,
+    upstream::Upstream,
     AppState,
 };
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct SkillGemsQuery {
+    /// League to fetch gem prices for, or `current` / `current-hc` for the active challenge league (default: `defaults.league`)
     league: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
 pub struct CalculationQuery {
-    league: Option<String>,
-    ignore_after_chaos: Option<f64>,
-    gem_level: Option<u32>,
-    gem_quality: Option<u32>,
+    /// League to fetch gem prices for, or `current` / `current-hc` for the active challenge league (default: `defaults.league`)
+    pub league: Option<String>,
+    /// Gems worth less than this many chaos count as 0 (default: `defaults.ignore_after_chaos`)
+    pub ignore_after_chaos: Option<f64>,
+    /// Gem level to price: 1, 20 or 21 (default: 1)
+    pub gem_level: Option<u32>,
+    /// Gem quality to price: 0, 20 or 23 (default: 0)
+    pub gem_quality: Option<u32>,
+    /// Also report the expected values in this currency, by poe.ninja name or as chaos, divine or exalted
+    pub currency: Option<String>,
+    /// Comma-separated gem names or `*` patterns to leave out of the pools, e.g. `Spark of *,Cleave of Rage`
+    pub exclude: Option<String>,
+    /// Comma-separated `NAME:CHAOS` prices to use instead of poe.ninja's; names may be `*` patterns
+    pub prices: Option<String>,
+    /// What to do with price-fixed outliers: off, cap, winsorize or exclude (default: `outliers.policy`)
+    pub outliers: Option<OutlierPolicy>,
+    /// Gems offered per craft, of which the best is kept (default: 3)
+    pub offer_size: Option<usize>,
+    /// Also compute the expected values on prices projected from each gem's 7-day trend (default: false)
+    pub trend: Option<bool>,
+    /// Hours ahead to project prices, up to 168; implies `trend` (default: `trend.hours`)
+    pub projection_hours: Option<f64>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    /// Response format: json, csv, tsv, markdown or discord (default: picked from the `Accept` header, otherwise json)
+    format: Option<ExportFormat>,
+}
+
+impl ExportQuery {
+    /// The requested format; `format` wins over the `Accept` header.
+    pub fn format(&self, headers: &HeaderMap) -> ExportFormat {
+        self.format
+            .or_else(|| {
+                headers
+                    .get(header::ACCEPT)
+                    .and_then(|accept| accept.to_str().ok())
+                    .and_then(ExportFormat::from_accept)
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Answers with the JSON response, or with its rendering in another export format.
+fn export_response<T: Serialize>(format: ExportFormat, value: T, render: impl FnOnce() -> String) -> Response {
+    // The format may come from the Accept header, so caches must keep them apart
+    let vary = [(header::VARY, "Accept")];
+    match format {
+        ExportFormat::Json => (vary, Json(value)).into_response(),
+        _ => (vary, [(header::CONTENT_TYPE, format.content_type())], render()).into_response(),
+    }
 }
 
+impl CalculationQuery {
+    /// Engine options for the query, filling in the configured defaults.
+    ///
+    /// Malformed prices, projection hours and an offer size of 0 are a bad request.
+    pub fn options(&self, state: &AppState) -> Result<CalculationOptions, StatusCode> {
+        let list = |value: &Option<String>| -> Vec<String> {
+            value
+                .iter()
+                .flat_map(|value| value.split(','))
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        let price_overrides = list(&self.prices)
+            .iter()
+            .map(|entry| parse_price_override(entry))
+            .collect::<Result<_, _>>()
+            .map_err(|e| {
+                warn!("Rejecting price overrides: {}", e);
+                StatusCode::BAD_REQUEST
+            })?;
+
+        let offer_size = self.offer_size.unwrap_or(DEFAULT_OFFER_SIZE);
+        if offer_size == 0 {
+            warn!("Rejecting offer size of 0");
+            return Err(StatusCode::BAD_REQUEST);
+        }
+
+        let trend = match (self.trend, self.projection_hours) {
+            (Some(false), _) | (None, None) => None,
+            (_, Some(hours)) if !(hours > 0.0 && hours <= MAX_PROJECTION_HOURS) => {
+                warn!("Rejecting projection hours: {}", hours);
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            (_, hours) => Some(TrendOptions {
+                hours: hours.unwrap_or(state.config.trend.hours),
+                ..state.config.trend.clone()
+            }),
+        };
+
+        Ok(CalculationOptions {
+            gem_level: self.gem_level.unwrap_or(1),
+            gem_quality: self.gem_quality.unwrap_or(0),
+            ignore_after_chaos: self.ignore_after_chaos.unwrap_or(state.config.defaults.ignore_after_chaos),
+            excluded: list(&self.exclude),
+            price_overrides,
+            outliers: OutlierOptions {
+                policy: self.outliers.unwrap_or(state.config.outliers.policy),
+                ..state.config.outliers.clone()
+            },
+            offer_size,
+            trend,
+        })
+    }
+}
+
+/// Returns the raw skill gem overview from POE Ninja for a league.
+///
+/// CSV, TSV, Markdown and Discord exports list every gem variant, most valuable first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/skill-gems",
+    tag = "gems",
+    params(SkillGemsQuery, ExportQuery),
+    responses(
+        (status = 200, description = "Skill gem overview, or its export in the requested format", content(
+            ("application/json" = SkillGemResponse),
+            ("text/csv" = String),
+            ("text/tab-separated-values" = String),
+            ("text/markdown" = String),
+            ("text/plain" = String)
+        )),
+        (status = 404, description = "POE Ninja has no data for the league"),
+        (status = 429, description = "Too many requests from this client or to POE Ninja; see Retry-After"),
+        (status = 502, description = "POE Ninja returned an error"),
+        (status = 500, description = "POE Ninja could not be reached or parsed")
+    )
+)]
 pub async fn get_skill_gems(
     Query(params): Query<SkillGemsQuery>,
+    Query(export): Query<ExportQuery>,
+    headers: HeaderMap,
     State(state): State<AppState>,
-) -> Result<Json<SkillGemResponse>, StatusCode> {
-    let league = params.league.unwrap_or_else(|| "Standard".to_string());
+) -> Result<Response, StatusCode> {
+    let league = params.league.unwrap_or_else(|| state.config.defaults.league.clone());
+    let league = resolve_league(&state, &league).await;
+    let (skill_gems_response, updated_at) = fetch_skill_gems_snapshot(&state, &league).await?;
+
+    let format = export.format(&headers);
+    let snapshot = PriceSnapshot { league: &league, updated_at };
+    Ok(export_response(format, &skill_gems_response, || {
+        render_skill_gems(format, &skill_gems_response, snapshot)
+    }))
+}
+
+/// Loads the skill gem overview for a league, serving it from the cache when possible.
+///
+/// On a cache miss the data is fetched from POE Ninja and cached for `cache.ttl_minutes`.
+/// Leagues POE Ninja has no data for are answered with 404 for
+/// `cache.unknown_league_ttl_minutes` without asking again, and 429 means the upstream
+/// request budget is used up.
+pub async fn fetch_skill_gems(state: &AppState, league: &str) -> Result<SkillGemResponse, StatusCode> {
+    Ok(fetch_skill_gems_snapshot(state, league).await?.0)
+}
+
+/// Like `fetch_skill_gems`, but also returns when the data was fetched from POE Ninja.
+///
+/// Every fresh fetch is announced on `state.snapshot_updates` so open streams can
+/// push recalculated results.
+pub async fn fetch_skill_gems_snapshot(
+    state: &AppState,
+    league: &str,
+) -> Result<(SkillGemResponse, DateTime<Utc>), StatusCode> {
     let cache_key = format!("skillGems_{}", league);
 
-    // Try to get from cache first (TTL: 1 hour)
-    if let Ok(Some(cached_gems)) = state.cache.get::<SkillGemResponse>(&cache_key).await {
+    // Try to get from cache first
+    if let Ok(Some(cached)) = state.cache.get_with_timestamp::<SkillGemResponse>(&cache_key).await {
         info!("Returning cached skill gems data for league: {}", league);
-        return Ok(Json(cached_gems));
+        return Ok(cached);
+    }
+
+    let unknown_key = format!("unknownLeague_{}", league);
+    if let Ok(Some(true)) = state.cache.get::<bool>(&unknown_key).await {
+        info!("League {} is known to have no data on POE Ninja", league);
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    if state.rate_limits.upstream.check(&()).is_err() {
+        warn!("Upstream request budget used up, not fetching skill gems for league: {}", league);
+        state.metrics.observe_rate_limited("upstream");
+        return Err(StatusCode::TOO_MANY_REQUESTS);
     }
 
     // Fetch fresh data from POE Ninja
     info!("Fetching fresh skill gems data for league: {}", league);
 
-    let url = format!(
-        "https://poe.ninja/api/data/itemoverview?league={}&type=SkillGem&language=en",
-        urlencoding::encode(&league)
-    );
-
-    let response = match state.client.get(&url).send().await {
-        Ok(response) => response,
-        Err(e) => {
-            error!("Failed to fetch skill gems from POE Ninja: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    let started = Instant::now();
+    let result = request_overview::<SkillGemResponse>(state, "itemoverview?type=SkillGem", league).await;
+    // Not knowing a league is a valid answer, not an upstream failure
+    let answered = match &result {
+        Ok(_) => true,
+        Err((status, _)) => *status == StatusCode::NOT_FOUND,
+    };
+    state
+        .metrics
+        .observe_upstream(Upstream::PoeNinja, started.elapsed(), answered);
+
+    let skill_gems_response = match result {
+        Ok(data) if !data.lines.is_empty() => {
+            state.upstreams.record_success(Upstream::PoeNinja);
+            data
+        }
+        Ok(_) | Err((StatusCode::NOT_FOUND, _)) => {
+            state.upstreams.record_success(Upstream::PoeNinja);
+            warn!("POE Ninja has no skill gem data for league: {}", league);
+            if let Err(e) = state
+                .cache
+                .set(&unknown_key, true, state.config.cache.unknown_league_ttl_minutes)
+                .await
+            {
+                warn!("Failed to remember unknown league {}: {}", league, e);
+            }
+            return Err(StatusCode::NOT_FOUND);
+        }
+        Err((status, message)) => {
+            error!("{}", message);
+            state.upstreams.record_failure(Upstream::PoeNinja, message);
+            return Err(status);
         }
     };
 
-    if !response.status().is_success() {
-        error!("POE Ninja returned error status: {}", response.status());
-        return Err(StatusCode::BAD_GATEWAY);
-    }
-
-    let skill_gems_response: SkillGemResponse = match response.json().await {
-        Ok(data) => data,
+    // Cache the response
+    let updated_at = match state.cache.set(&cache_key, &skill_gems_response, state.config.cache.ttl_minutes).await {
+        Ok(timestamp) => timestamp,
         Err(e) => {
-            error!("Failed to parse skill gems response: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            error!("Failed to cache skill gems data: {}", e);
+            // Continue anyway, don't fail the request
+            Utc::now()
         }
     };
 
-    // Cache the response for 1 hour
-    if let Err(e) = state.cache.set(&cache_key, &skill_gems_response, 60).await {
-        error!("Failed to cache skill gems data: {}", e);
-        // Continue anyway, don't fail the request
-    }
-
     info!(
         "Successfully fetched and cached {} skill gems for league: {}",
         skill_gems_response.lines.len(),
         league
     );
 
-    Ok(Json(skill_gems_response))
-}
+    // Nobody listening is fine
+    let _ = state.snapshot_updates.send(SnapshotUpdate {
+        league: league.to_string(),
+        updated_at,
+    });
 
-pub async fn calculate_gem_roi(
-    Query(params): Query<CalculationQuery>,
-    State(state): State<AppState>,
-) -> Result<Json<CalculationResponse>, StatusCode> {
-    let league = params.league.unwrap_or_else(|| "Standard".to_string());
-    let ignore_after_chaos = params.ignore_after_chaos.unwrap_or(5.0);
-    let gem_level = params.gem_level.unwrap_or(1);
-    let gem_quality = params.gem_quality.unwrap_or(0);
+    Ok((skill_gems_response, updated_at))
+}
 
-    info!(
-        "Calculating ROI for league: {}, level: {}, quality: {}, ignore_threshold: {}",
-        league, gem_level, gem_quality, ignore_after_chaos
+/// Performs a POE Ninja overview request such as `itemoverview?type=SkillGem`,
+/// returning the status to answer with and a log message on failure.
+pub(crate) async fn request_overview<T: DeserializeOwned>(
+    state: &AppState,
+    overview: &str,
+    league: &str,
+) -> Result<T, (StatusCode, String)> {
+    let url = format!(
+        "{}/api/data/{}&league={}&language=en",
+        state.config.upstream.poe_ninja_url,
+        overview,
+        urlencoding::encode(league)
     );
 
-    // Get skill gems data
-    let cache_key = format!("skillGems_{}", league);
-    let skill_gems_response = if let Ok(Some(cached_gems)) = state.cache.get::<SkillGemResponse>(&cache_key).await {
-        cached_gems
-    } else {
-        // Fetch fresh data if not cached
-        let url = format!(
-            "https://poe.ninja/api/data/itemoverview?league={}&type=SkillGem&language=en",
-            urlencoding::encode(&league)
-        );
-
-        let response = match state.client.get(&url).send().await {
-            Ok(response) => response,
-            Err(e) => {
-                error!("Failed to fetch skill gems from POE Ninja: {}", e);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
-            }
-        };
+    let response = state.client.get(&url).send().await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to fetch {} from POE Ninja: {}", overview, e),
+        )
+    })?;
+
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("POE Ninja does not know league: {}", league),
+        ));
+    }
 
-        let gems_response: SkillGemResponse = match response.json().await {
-            Ok(data) => data,
-            Err(e) => {
-                error!("Failed to parse skill gems response: {}", e);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
-            }
-        };
+    if !response.status().is_success() {
+        return Err((
+            StatusCode::BAD_GATEWAY,
+            format!("POE Ninja returned error status: {}", response.status()),
+        ));
+    }
 
-        // Cache for future use
-        if let Err(e) = state.cache.set(&cache_key, &gems_response, 60).await {
-            warn!("Failed to cache skill gems data: {}", e);
-        }
+    response.json().await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to parse {} response: {}", overview, e),
+        )
+    })
+}
 
-        gems_response
-    };
+/// What a calculation uses for the parameters a query leaves out, for clients that
+/// run the engine themselves, like the web UI's WebAssembly build.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CalculationDefaults {
+    pub ignore_after_chaos: f64,
+    pub offer_size: usize,
+    /// `[outliers]` config: `policy` and detection thresholds
+    #[schema(value_type = Object)]
+    pub outliers: OutlierOptions,
+    /// `[trend]` config: `hours`, `max_change_percent` and `decline_percent`
+    #[schema(value_type = Object)]
+    pub trend: TrendOptions,
+    /// Base URLs the `trade_url` and `ninja_url` links are built from
+    pub trade_url: String,
+    pub poe_ninja_url: String,
+}
 
-    // Categorize gems by color and filter by criteria
-    let mut red_gems = Vec::new();
-    let mut green_gems = Vec::new();
-    let mut blue_gems = Vec::new();
-
-    for gem in skill_gems_response.lines {
-        // Filter gems based on criteria
-        let matches_level = gem.gem_level == Some(gem_level) || (gem_level == 1 && gem.gem_level.is_none());
-        let matches_quality = if gem_quality == 0 {
-            gem.gem_quality.is_none() || gem.gem_quality == Some(0)
-        } else {
-            gem.gem_quality == Some(gem_quality)
-        };
-        let matches_corruption = if gem_level > 20 || gem_quality > 20 {
-            gem.corrupted == Some(true)
-        } else {
-            gem.corrupted.is_none() || gem.corrupted == Some(false)
-        };
+/// Returns the configured defaults of `/api/v1/calculate`, so a calculation run
+/// locally on `/api/v1/skill-gems` data matches the server's.
+#[utoipa::path(
+    get,
+    path = "/api/v1/calculate/defaults",
+    tag = "calculation",
+    responses(
+        (status = 200, description = "Defaults of the calculation options", body = CalculationDefaults)
+    )
+)]
+pub async fn get_calculation_defaults(State(state): State<AppState>) -> Json<CalculationDefaults> {
+    Json(CalculationDefaults {
+        ignore_after_chaos: state.config.defaults.ignore_after_chaos,
+        offer_size: DEFAULT_OFFER_SIZE,
+        outliers: state.config.outliers.clone(),
+        trend: state.config.trend.clone(),
+        trade_url: state.config.upstream.trade_url.clone(),
+        poe_ninja_url: state.config.upstream.poe_ninja_url.clone(),
+    })
+}
 
-        // Must have trade filter to be considered
-        if gem.trade_filter.is_some() && matches_level && matches_quality && matches_corruption {
-            let chaos_value = gem.chaos_value.unwrap_or(0.0);
-
-            // Only process transfigured gems (contain " of " in name)
-            if is_transfigured_gem(&gem.name) {
-                // Extract color from icon URL
-                if let Some(icon_url) = &gem.icon {
-                    if let Some(color) = GemColor::from_icon_url(icon_url) {
-                        let gem_data = (gem.name.clone(), chaos_value);
-                        match color {
-                            GemColor::Red => red_gems.push(gem_data),
-                            GemColor::Green => green_gems.push(gem_data),
-                            GemColor::Blue => blue_gems.push(gem_data),
-                        }
-                    }
-                }
-            }
-        }
+/// Calculates the expected value of transfiguring a gem of each color.
+///
+/// CSV, TSV, Markdown and Discord exports list the expected value per color and
+/// every gem's value and probability, with the time of the price snapshot.
+#[utoipa::path(
+    get,
+    path = "/api/v1/calculate",
+    tag = "calculation",
+    params(CalculationQuery, ExportQuery),
+    responses(
+        (status = 200, description = "Expected value per gem color, or its export in the requested format", content(
+            ("application/json" = CalculationResponse),
+            ("text/csv" = String),
+            ("text/tab-separated-values" = String),
+            ("text/markdown" = String),
+            ("text/plain" = String)
+        )),
+        (status = 400, description = "Unknown currency, malformed prices, projection hours or offer size"),
+        (status = 404, description = "POE Ninja has no data for the league, or no price for the currency"),
+        (status = 429, description = "Too many requests from this client or to POE Ninja; see Retry-After"),
+        (status = 502, description = "POE Ninja returned an error"),
+        (status = 500, description = "POE Ninja could not be reached or parsed")
+    )
+)]
+pub async fn calculate_gem_roi(
+    Query(params): Query<CalculationQuery>,
+    Query(export): Query<ExportQuery>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Response, StatusCode> {
+    let Calculation { league, options, response, updated_at, .. } = run_calculation(&state, params).await?;
+
+    // Only the default calculation is the league's expected value; any client could
+    // otherwise overwrite the series with exclusions, overrides or other settings
+    if options.excluded.is_empty()
+        && options.price_overrides.is_empty()
+        && options.outliers.policy == state.config.outliers.policy
+        && options.ignore_after_chaos == state.config.defaults.ignore_after_chaos
+        && options.offer_size == DEFAULT_OFFER_SIZE
+    {
+        let (gem_level, gem_quality) = (options.gem_level, options.gem_quality);
+        state.metrics.set_expected_value(&league, GemColor::Red.as_str(), gem_level, gem_quality, response.red_roi);
+        state.metrics.set_expected_value(&league, GemColor::Green.as_str(), gem_level, gem_quality, response.green_roi);
+        state.metrics.set_expected_value(&league, GemColor::Blue.as_str(), gem_level, gem_quality, response.blue_roi);
     }
 
-    // Sort gems by value (highest first)
-    red_gems.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-    green_gems.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-    blue_gems.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-
-    info!(
-        "Gem counts - Red: {}, Green: {}, Blue: {}",
-        red_gems.len(), green_gems.len(), blue_gems.len()
-    );
-
-    // Calculate probabilities and ROI
-    let red_roi = calculate_roi_for_gems(&red_gems, ignore_after_chaos);
-    let green_roi = calculate_roi_for_gems(&green_gems, ignore_after_chaos);
-    let blue_roi = calculate_roi_for_gems(&blue_gems, ignore_after_chaos);
-
-    // Prepare detailed gem information
-    let red_probabilities = calculate_probability(red_gems.len());
-    let green_probabilities = calculate_probability(green_gems.len());
-    let blue_probabilities = calculate_probability(blue_gems.len());
-
-    let red_gem_values = create_gem_values(&red_gems, &red_probabilities);
-    let green_gem_values = create_gem_values(&green_gems, &green_probabilities);
-    let blue_gem_values = create_gem_values(&blue_gems, &blue_probabilities);
-
-    let response = CalculationResponse {
-        red_roi,
-        green_roi,
-        blue_roi,
-        red_gems: red_gem_values,
-        green_gems: green_gem_values,
-        blue_gems: blue_gem_values,
-    };
-
     info!(
         "ROI calculation complete - Red: {:.2}, Green: {:.2}, Blue: {:.2}",
-        red_roi, green_roi, blue_roi
+        response.red_roi, response.green_roi, response.blue_roi
     );
 
-    Ok(Json(response))
+    let format = export.format(&headers);
+    let snapshot = PriceSnapshot { league: &league, updated_at };
+    Ok(export_response(format, &response, || {
+        render_calculation(format, &response, snapshot, options.gem_level, options.gem_quality)
+    }))
 }
 
-fn calculate_probability(n: usize) -> Vec<f64> {
-    if n < 3 {
-        return vec![];
-    }
-
-    let mut probabilities = Vec::new();
-    let total_outcomes = (n * (n - 1) * (n - 2)) as f64 / 6.0;
-
-    for i in (3..=n).rev() {
-        let favorable_outcomes = ((i - 1) * (i - 2)) as f64 / 2.0;
-        let probability = favorable_outcomes / total_outcomes;
-        probabilities.push(probability);
-    }
-
-    probabilities
+/// A finished calculation together with everything it was computed from.
+pub struct Calculation {
+    pub league: String,
+    /// The query with the league resolved and every default filled in, so running
+    /// it again gives the same result on the same prices
+    pub inputs: CalculationQuery,
+    pub options: CalculationOptions,
+    pub snapshot: SkillGemResponse,
+    /// When the price snapshot was fetched from POE Ninja
+    pub updated_at: DateTime<Utc>,
+    pub response: CalculationResponse,
 }
 
-fn calculate_roi_for_gems(gems: &[(String, f64)], ignore_threshold: f64) -> f64 {
-    if gems.len() < 3 {
-        return 0.0;
-    }
-
-    let probabilities = calculate_probability(gems.len());
-    let mut roi = 0.0;
+/// Runs a calculation for a query, with links and the optional currency conversion.
+pub async fn run_calculation(state: &AppState, params: CalculationQuery) -> Result<Calculation, StatusCode> {
+    let options = params.options(state)?;
+    let (gem_level, gem_quality) = (options.gem_level, options.gem_quality);
+    let league = params.league.clone().unwrap_or_else(|| state.config.defaults.league.clone());
+    let league = resolve_league(state, &league).await;
 
-    for (i, (_, value)) in gems.iter().enumerate() {
-        if i >= probabilities.len() {
-            break;
-        }
+    info!(
+        "Calculating ROI for league: {}, level: {}, quality: {}, ignore_threshold: {}, excluded: {:?}, overrides: {:?}",
+        league, gem_level, gem_quality, options.ignore_after_chaos, options.excluded, options.price_overrides
+    );
 
-        let gem_value = if *value >= ignore_threshold { *value } else { 0.0 };
-        roi += probabilities[i] * gem_value;
+    // Get skill gems data
+    let (skill_gems_response, updated_at) = fetch_skill_gems_snapshot(state, &league).await?;
+
+    let mut response = calculate_with_options(&skill_gems_response, &options);
+    add_links(
+        &mut response,
+        &skill_gems_response,
+        &league,
+        gem_level,
+        gem_quality,
+        &state.config.upstream.trade_url,
+        &state.config.upstream.poe_ninja_url,
+    );
+    if let Some(currency) = &params.currency {
+        let (currency, chaos_equivalent) = conversion_rate(state, &league, currency).await?;
+        response.converted = Some(response.in_currency(&currency, chaos_equivalent));
     }
 
-    roi
-}
+    let inputs = CalculationQuery {
+        league: Some(league.clone()),
+        ignore_after_chaos: Some(options.ignore_after_chaos),
+        gem_level: Some(gem_level),
+        gem_quality: Some(gem_quality),
+        offer_size: Some(options.offer_size),
+        outliers: Some(options.outliers.policy),
+        trend: Some(options.trend.is_some()),
+        projection_hours: options.trend.as_ref().map(|trend| trend.hours),
+        ..params
+    };
 
-fn create_gem_values(gems: &[(String, f64)], probabilities: &[f64]) -> Vec<GemValue> {
-    gems.iter()
-        .zip(probabilities.iter())
-        .map(|((name, chaos_value), probability)| GemValue {
-            name: name.clone(),
-            chaos_value: *chaos_value,
-            probability: *probability,
-        })
-        .collect()
+    Ok(Calculation {
+        league,
+        inputs,
+        options,
+        snapshot: skill_gems_response,
+        updated_at,
+        response,
+    })
 }
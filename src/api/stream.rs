@@ -0,0 +1,145 @@
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use chrono::{DateTime, Utc};
+use futures_core::Stream;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+use crate::{
+    api::{
+        currency::conversion_rate,
+        leagues::resolve_league,
+        skill_gems::{fetch_skill_gems_snapshot, CalculationQuery},
+    },
+    engine::calculate_with_options,
+    links::add_links,
+    models::CalculationUpdate,
+    AppState,
+};
+
+/// How often an open stream checks whether its league's cached prices expired and
+/// need to be refetched.
+const REFRESH_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often a comment is sent on idle streams to keep proxies from closing them.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How long clients should wait before reconnecting after the stream drops.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Announces that a league's skill gem prices were fetched from POE Ninja.
+#[derive(Debug, Clone)]
+pub struct SnapshotUpdate {
+    pub league: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Server-Sent Events stream of per-color results for one league and filter set.
+///
+/// A `calculation` event is pushed on connect and again every time the league's
+/// price snapshot is refreshed. Event ids are the snapshot's fetch time in
+/// milliseconds, so a client reconnecting with `Last-Event-ID` only receives a new
+/// event once newer prices are available.
+#[utoipa::path(
+    get,
+    path = "/api/v1/calculate/stream",
+    tag = "calculation",
+    params(CalculationQuery),
+    responses(
+        (status = 200, description = "Stream of `calculation` events", content_type = "text/event-stream", body = CalculationUpdate),
+        (status = 400, description = "Malformed prices"),
+        (status = 429, description = "Too many requests from this client; see Retry-After")
+    )
+)]
+pub async fn stream_calculation(
+    Query(params): Query<CalculationQuery>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, StatusCode> {
+    let options = params.options(&state)?;
+    let league = params.league.unwrap_or_else(|| state.config.defaults.league.clone());
+    let league = resolve_league(&state, &league).await;
+
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<i64>().ok());
+
+    info!(
+        "Opening calculation stream for league: {}, level: {}, quality: {}, resuming from: {:?}",
+        league, options.gem_level, options.gem_quality, last_event_id
+    );
+
+    let mut updates = state.snapshot_updates.subscribe();
+
+    let stream = async_stream::stream! {
+        let mut last_sent = last_event_id;
+        let mut ticker = tokio::time::interval(REFRESH_CHECK_INTERVAL);
+
+        loop {
+            // The first tick completes immediately, which sends the initial result
+            tokio::select! {
+                _ = ticker.tick() => {}
+                update = updates.recv() => match update {
+                    Ok(update) if update.league != league => continue,
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                },
+                // End the stream so the server can finish draining connections
+                _ = state.shutdown.wait() => break,
+            }
+
+            let (skill_gems, updated_at) = match fetch_skill_gems_snapshot(&state, &league).await {
+                Ok(snapshot) => snapshot,
+                Err(status) => {
+                    warn!("Calculation stream for {} could not load prices: {}", league, status);
+                    yield Ok(Event::default().event("error").data(status.to_string()));
+                    continue;
+                }
+            };
+
+            let id = updated_at.timestamp_millis();
+            if last_sent.is_some_and(|sent| id <= sent) {
+                continue;
+            }
+
+            let mut result = calculate_with_options(&skill_gems, &options);
+            add_links(
+                &mut result,
+                &skill_gems,
+                &league,
+                options.gem_level,
+                options.gem_quality,
+                &state.config.upstream.trade_url,
+                &state.config.upstream.poe_ninja_url,
+            );
+            if let Some(currency) = &params.currency {
+                match conversion_rate(&state, &league, currency).await {
+                    Ok((currency, chaos_equivalent)) => {
+                        result.converted = Some(result.in_currency(&currency, chaos_equivalent));
+                    }
+                    Err(status) => warn!("Calculation stream for {} could not convert to {}: {}", league, currency, status),
+                }
+            }
+
+            let update = CalculationUpdate {
+                league: league.clone(),
+                updated_at,
+                result,
+            };
+            last_sent = Some(id);
+
+            yield Event::default()
+                .event("calculation")
+                .id(id.to_string())
+                .retry(RECONNECT_DELAY)
+                .json_data(&update);
+        }
+    };
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::new().interval(HEARTBEAT_INTERVAL)))
+}
@@ -0,0 +1,90 @@
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use rust_embed::RustEmbed;
+
+/// The web frontend (`public/`), compiled into the binary so it doesn't depend on
+/// the working directory. `server.static_dir` serves it from disk instead.
+#[derive(RustEmbed)]
+#[folder = "public/"]
+struct Frontend;
+
+/// HTML is revalidated on every load so a new release shows up straight away.
+const HTML_CACHE_CONTROL: &str = "no-cache";
+
+/// Scripts, images and the like aren't fingerprinted, so they are only cached for
+/// an hour and revalidated with their ETag after that.
+const ASSET_CACHE_CONTROL: &str = "public, max-age=3600";
+
+/// Serves an embedded frontend file. Directory paths get their `index.html`.
+pub async fn serve_embedded(uri: Uri, headers: HeaderMap) -> Response {
+    let path = uri.path().trim_start_matches('/');
+    let path = if path.is_empty() || path.ends_with('/') {
+        format!("{}index.html", path)
+    } else {
+        path.to_string()
+    };
+
+    serve_file(&path, &headers)
+}
+
+/// Serves the frontend's `index.html` for pages it renders itself, such as the
+/// permalinks of saved calculations.
+pub async fn serve_embedded_index(headers: HeaderMap) -> Response {
+    serve_file("index.html", &headers)
+}
+
+fn serve_file(path: &str, headers: &HeaderMap) -> Response {
+    let Some(file) = Frontend::get(path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let etag = format!("\"{}\"", hex(&file.metadata.sha256_hash()));
+    let mime = file.metadata.mimetype();
+    let cache_control = if mime.starts_with("text/html") {
+        HTML_CACHE_CONTROL
+    } else {
+        ASSET_CACHE_CONTROL
+    };
+
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"));
+
+    let mut response = if not_modified {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        Response::new(Body::from(file.data.into_owned()))
+    };
+
+    let response_headers = response.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(mime) {
+        response_headers.insert(header::CONTENT_TYPE, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        response_headers.insert(header::ETAG, value);
+    }
+    response_headers.insert(header::CACHE_CONTROL, HeaderValue::from_static(cache_control));
+
+    response
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frontend_is_embedded() {
+        for path in ["index.html", "app.js", "robots.txt", "sitemap.xml", "imgs/HeraldofAsh.png"] {
+            assert!(Frontend::get(path).is_some(), "{} is not embedded", path);
+        }
+        assert_eq!(Frontend::get("app.js").unwrap().metadata.mimetype(), "text/javascript");
+    }
+}
@@ -1,20 +1,28 @@
 use anyhow::{Context, Result};
 use chrono::{DateTime, Duration, Utc};
+use prometheus::{IntCounterVec, Opts};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::{Path, PathBuf};
-use tracing::{debug, error, info, warn};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::Notify;
+use tracing::{debug, error, info};
+use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct CacheEntry<T> {
+    /// The key as given, since file names are sanitized; empty in older files
+    #[serde(default)]
+    key: String,
     data: T,
     timestamp: DateTime<Utc>,
     ttl_minutes: i64,
 }
 
 impl<T> CacheEntry<T> {
-    fn new(data: T, ttl_minutes: i64) -> Self {
+    fn new(key: &str, data: T, ttl_minutes: i64) -> Self {
         Self {
+            key: key.to_string(),
             data,
             timestamp: Utc::now(),
             ttl_minutes,
@@ -32,8 +40,48 @@ impl<T> CacheEntry<T> {
     }
 }
 
+/// Metadata about a cached entry, without its payload.
+#[derive(Debug, Clone)]
+pub struct CacheEntryInfo {
+    pub key: String,
+    pub timestamp: DateTime<Utc>,
+    pub ttl_minutes: i64,
+    pub expired: bool,
+}
+
+/// Header fields of a cache file, read without deserializing the data.
+#[derive(Deserialize)]
+struct CacheEntryHeader {
+    #[serde(default)]
+    key: String,
+    timestamp: DateTime<Utc>,
+    ttl_minutes: i64,
+}
+
 pub struct FileCache {
     cache_dir: PathBuf,
+    lookups: IntCounterVec,
+    /// Writes that have started but not yet been renamed into place
+    pending_writes: AtomicUsize,
+    writes_done: Notify,
+}
+
+/// Marks a write as pending for as long as it is alive.
+struct PendingWrite<'a>(&'a FileCache);
+
+impl<'a> PendingWrite<'a> {
+    fn start(cache: &'a FileCache) -> Self {
+        cache.pending_writes.fetch_add(1, Ordering::SeqCst);
+        Self(cache)
+    }
+}
+
+impl Drop for PendingWrite<'_> {
+    fn drop(&mut self) {
+        if self.0.pending_writes.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.writes_done.notify_waiters();
+        }
+    }
 }
 
 impl FileCache {
@@ -47,10 +95,42 @@ impl FileCache {
             info!("Created cache directory: {:?}", cache_dir);
         }
 
-        Ok(Self { cache_dir })
+        let lookups = IntCounterVec::new(
+            Opts::new("cache_lookups_total", "Cache lookups by key type and result (hit, miss, stale)"),
+            &["key_type", "result"],
+        )?;
+
+        Ok(Self {
+            cache_dir,
+            lookups,
+            pending_writes: AtomicUsize::new(0),
+            writes_done: Notify::new(),
+        })
+    }
+
+    /// Counter of lookups by key type and result, for registration with the metrics registry.
+    pub fn lookup_counter(&self) -> &IntCounterVec {
+        &self.lookups
+    }
+
+    fn record_lookup(&self, key: &str, result: &str) {
+        // Keys look like "skillGems_<league>"; the prefix identifies the kind of data
+        let key_type = key.split('_').next().unwrap_or(key);
+        self.lookups.with_label_values(&[key_type, result]).inc();
     }
 
     pub async fn get<T>(&self, key: &str) -> Result<Option<T>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        Ok(self.get_with_timestamp(key).await?.map(|(data, _)| data))
+    }
+
+    /// Like `get`, but also returns when the entry was written.
+    ///
+    /// Expired entries are a miss but stay on disk, so [`FileCache::entries`] can still
+    /// report them as stale, until [`FileCache::cleanup_expired`] or a new `set`.
+    pub async fn get_with_timestamp<T>(&self, key: &str) -> Result<Option<(T, DateTime<Utc>)>>
     where
         T: for<'de> Deserialize<'de>,
     {
@@ -58,6 +138,7 @@ impl FileCache {
 
         if !file_path.exists() {
             debug!("Cache miss: {} (file does not exist)", key);
+            self.record_lookup(key, "miss");
             return Ok(None);
         }
 
@@ -75,11 +156,7 @@ impl FileCache {
                 cache_entry.ttl_minutes
             );
 
-            // Clean up expired cache file
-            if let Err(e) = fs::remove_file(&file_path) {
-                warn!("Failed to remove expired cache file {:?}: {}", file_path, e);
-            }
-
+            self.record_lookup(key, "stale");
             return Ok(None);
         }
 
@@ -90,24 +167,44 @@ impl FileCache {
             cache_entry.ttl_minutes
         );
 
-        Ok(Some(cache_entry.data))
+        self.record_lookup(key, "hit");
+        Ok(Some((cache_entry.data, cache_entry.timestamp)))
     }
 
-    pub async fn set<T>(&self, key: &str, data: T, ttl_minutes: i64) -> Result<()>
+    /// Stores an entry and returns the timestamp it was written with.
+    ///
+    /// The entry is written to a temporary file and renamed into place, so readers
+    /// and a process stopped mid-write never see a partial file.
+    pub async fn set<T>(&self, key: &str, data: T, ttl_minutes: i64) -> Result<DateTime<Utc>>
     where
         T: Serialize,
     {
-        let cache_entry = CacheEntry::new(data, ttl_minutes);
+        let _pending = PendingWrite::start(self);
+        let cache_entry = CacheEntry::new(key, data, ttl_minutes);
         let file_path = self.get_cache_path(key);
+        let temp_path = temp_path(&file_path);
 
         let content = serde_json::to_string_pretty(&cache_entry)
             .with_context(|| format!("Failed to serialize cache entry for key: {}", key))?;
 
-        fs::write(&file_path, content)
-            .with_context(|| format!("Failed to write cache file: {:?}", file_path))?;
+        fs::write(&temp_path, content)
+            .with_context(|| format!("Failed to write cache file: {:?}", temp_path))?;
+        fs::rename(&temp_path, &file_path)
+            .with_context(|| format!("Failed to move cache file into place: {:?}", file_path))?;
 
         debug!("Cached data for key: {} (ttl: {} minutes)", key, ttl_minutes);
-        Ok(())
+        Ok(cache_entry.timestamp)
+    }
+
+    /// Waits until every write that has started is on disk.
+    pub async fn flush(&self) {
+        loop {
+            let done = self.writes_done.notified();
+            if self.pending_writes.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            done.await;
+        }
     }
 
     pub async fn delete(&self, key: &str) -> Result<()> {
@@ -163,6 +260,14 @@ impl FileCache {
             let entry = entry.with_context(|| "Failed to read directory entry")?;
             let path = entry.path();
 
+            // Leftover from a write that never finished
+            if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("tmp") {
+                if let Err(e) = fs::remove_file(&path) {
+                    error!("Failed to remove partial cache file {:?}: {}", path, e);
+                }
+                continue;
+            }
+
             if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("json") {
                 // Try to read and check if expired
                 if let Ok(content) = fs::read_to_string(&path) {
@@ -186,6 +291,78 @@ impl FileCache {
         Ok(count)
     }
 
+    /// Lists every cache entry, expired ones included, with its age and expiry,
+    /// skipping unreadable files.
+    pub async fn entries(&self) -> Result<Vec<CacheEntryInfo>> {
+        let mut infos = Vec::new();
+
+        if !self.cache_dir.exists() {
+            return Ok(infos);
+        }
+
+        let entries = fs::read_dir(&self.cache_dir)
+            .with_context(|| format!("Failed to read cache directory: {:?}", self.cache_dir))?;
+
+        for entry in entries {
+            let entry = entry.with_context(|| "Failed to read directory entry")?;
+            let path = entry.path();
+
+            if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("json") {
+                let Some(file_stem) = path.file_stem().and_then(|s| s.to_str()) else {
+                    continue;
+                };
+                if let Ok(content) = fs::read_to_string(&path) {
+                    if let Ok(header) = serde_json::from_str::<CacheEntryHeader>(&content) {
+                        let expired = Utc::now() > header.timestamp + Duration::minutes(header.ttl_minutes);
+                        // Files written before keys were stored only have the sanitized one
+                        let key = if header.key.is_empty() { file_stem.to_string() } else { header.key };
+                        infos.push(CacheEntryInfo {
+                            key,
+                            timestamp: header.timestamp,
+                            ttl_minutes: header.ttl_minutes,
+                            expired,
+                        });
+                    }
+                }
+            }
+        }
+
+        infos.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(infos)
+    }
+
+    /// Total size in bytes of all files in the cache directory.
+    pub async fn size_bytes(&self) -> Result<u64> {
+        if !self.cache_dir.exists() {
+            return Ok(0);
+        }
+
+        let entries = fs::read_dir(&self.cache_dir)
+            .with_context(|| format!("Failed to read cache directory: {:?}", self.cache_dir))?;
+
+        let mut total = 0;
+        for entry in entries {
+            let entry = entry.with_context(|| "Failed to read directory entry")?;
+            if let Ok(metadata) = entry.metadata() {
+                if metadata.is_file() {
+                    total += metadata.len();
+                }
+            }
+        }
+
+        Ok(total)
+    }
+
+    /// Verifies the cache directory accepts writes by creating and removing a probe file.
+    pub async fn check_writable(&self) -> Result<()> {
+        let probe = temp_path(&self.cache_dir.join(".write_probe"));
+        fs::write(&probe, b"ok")
+            .with_context(|| format!("Cache directory is not writable: {:?}", self.cache_dir))?;
+        fs::remove_file(&probe)
+            .with_context(|| format!("Failed to remove cache probe file: {:?}", probe))?;
+        Ok(())
+    }
+
     fn get_cache_path(&self, key: &str) -> PathBuf {
         // Sanitize the key to create a valid filename
         let sanitized_key = key
@@ -200,6 +377,15 @@ impl FileCache {
     }
 }
 
+/// A temporary file next to `path`, unique to this write so concurrent writes of the
+/// same file don't share it. It ends in `.tmp`, so `FileCache::cleanup_expired`
+/// removes any an interrupted write leaves behind.
+pub(crate) fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.tmp", Uuid::new_v4().simple()));
+    path.with_file_name(name)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -223,6 +409,11 @@ mod tests {
         cache.delete("test_key").await.unwrap();
         let result: Option<String> = cache.get("test_key").await.unwrap();
         assert!(result.is_none());
+
+        // Lookups are counted by the key prefix
+        let lookups = cache.lookup_counter();
+        assert_eq!(lookups.with_label_values(&["test", "miss"]).get(), 2);
+        assert_eq!(lookups.with_label_values(&["test", "hit"]).get(), 1);
     }
 
     #[tokio::test]
@@ -238,6 +429,27 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[tokio::test]
+    async fn test_cache_entries_and_writable() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = FileCache::new(temp_dir.path()).unwrap();
+
+        cache.check_writable().await.unwrap();
+
+        cache.set("skillGems_Foo (PL12345)", "fresh".to_string(), 60).await.unwrap();
+        cache.set("leagues", "stale".to_string(), 0).await.unwrap();
+
+        // A stale lookup is a miss but leaves the entry listed
+        assert!(cache.get::<String>("leagues").await.unwrap().is_none());
+
+        let entries = cache.entries().await.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].key, "leagues");
+        assert!(entries[0].expired);
+        assert_eq!(entries[1].key, "skillGems_Foo (PL12345)");
+        assert!(!entries[1].expired);
+    }
+
     #[tokio::test]
     async fn test_cache_clear() {
         let temp_dir = TempDir::new().unwrap();
@@ -257,4 +469,35 @@ mod tests {
         assert!(result1.is_none());
         assert!(result2.is_none());
     }
+
+    #[tokio::test]
+    async fn test_cache_writes_are_atomic() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = FileCache::new(temp_dir.path()).unwrap();
+
+        cache.set("key1", "value1".to_string(), 60).await.unwrap();
+        cache.flush().await;
+
+        let files: Vec<_> = fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(files, vec!["key1.json".to_string()]);
+
+        // A write interrupted before the rename is cleaned up on the next start
+        fs::write(temp_dir.path().join("key2.json.tmp"), "{\"data\":").unwrap();
+        cache.cleanup_expired().await.unwrap();
+        assert!(!temp_dir.path().join("key2.json.tmp").exists());
+        assert!(cache.get::<String>("key2").await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_temp_paths_are_unique() {
+        let path = Path::new("cache/key.json");
+        let (first, second) = (temp_path(path), temp_path(path));
+        assert_ne!(first, second);
+        assert_eq!(first.parent(), path.parent());
+        assert!(first.file_name().unwrap().to_str().unwrap().starts_with("key.json."));
+        assert_eq!(first.extension().unwrap(), "tmp");
+    }
 }
@@ -0,0 +1,572 @@
+use anyhow::{anyhow, bail, Result};
+use clap::{Args, Subcommand, ValueEnum};
+use serde::Serialize;
+
+use poe_gem_calculator::{
+    api::{
+        leagues::{fetch_leagues, resolve_league, REALMS},
+        skill_gems::{fetch_skill_gems, run_calculation, CalculationQuery},
+    },
+    config::LoadedConfig,
+    engine::{outliers::OutlierPolicy, parse_price_override, trend::MAX_PROJECTION_HOURS},
+    models::{is_transfigured_gem, CalculationResponse, GemColor, GemValue, SkillGem},
+    AppState,
+};
+
+/// How command output is printed.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct CalculateArgs {
+    /// League to calculate for, or `current` / `current-hc` [default: defaults.league]
+    #[arg(long)]
+    league: Option<String>,
+
+    /// Gem level to price: 1, 20 or 21
+    #[arg(long, default_value = "1")]
+    gem_level: u32,
+
+    /// Gem quality to price: 0, 20 or 23
+    #[arg(long, default_value = "0")]
+    gem_quality: u32,
+
+    /// Gems worth less than this many chaos count as 0 [default: defaults.ignore_after_chaos]
+    #[arg(long)]
+    ignore_after_chaos: Option<f64>,
+
+    /// Also report the expected values in this currency: chaos, divine, exalted or a poe.ninja currency name
+    #[arg(long)]
+    currency: Option<String>,
+
+    /// Gem name or `*` pattern to leave out of the pools; repeatable
+    #[arg(long, value_name = "NAME")]
+    exclude: Vec<String>,
+
+    /// Price to use instead of poe.ninja's, as NAME:CHAOS (NAME may be a `*` pattern); repeatable
+    #[arg(long, value_name = "NAME:CHAOS", value_parser = parse_price_override)]
+    price: Vec<(String, f64)>,
+
+    /// What to do with price-fixed outliers: off, cap, winsorize or exclude [default: outliers.policy]
+    #[arg(long, value_name = "POLICY")]
+    outliers: Option<OutlierPolicy>,
+
+    /// Also compute the expected values on prices projected from each gem's 7-day trend
+    #[arg(long)]
+    trend: bool,
+
+    /// Hours ahead to project prices, up to 168; implies --trend [default: trend.hours]
+    #[arg(long, value_name = "HOURS", value_parser = parse_projection_hours)]
+    projection_hours: Option<f64>,
+
+    /// Also list every gem with its value and probability
+    #[arg(long)]
+    details: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct GemsArgs {
+    /// League to list gems for, or `current` / `current-hc` [default: defaults.league]
+    #[arg(long)]
+    league: Option<String>,
+
+    /// Only gems whose name contains this text (case-insensitive)
+    #[arg(long)]
+    name: Option<String>,
+
+    /// Only gems of this color
+    #[arg(long, value_parser = ["red", "green", "blue"])]
+    color: Option<String>,
+
+    /// Only transfigured gems
+    #[arg(long)]
+    transfigured: bool,
+
+    /// Only this gem level
+    #[arg(long)]
+    gem_level: Option<u32>,
+
+    /// Only this gem quality
+    #[arg(long)]
+    gem_quality: Option<u32>,
+
+    /// Only gems worth at least this many chaos
+    #[arg(long)]
+    min_chaos: Option<f64>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct LeaguesArgs {
+    /// Realm to list leagues for: pc, xbox or sony
+    #[arg(long, default_value = "pc")]
+    realm: String,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct FetchArgs {
+    /// Leagues to fetch prices for (default: every listed league)
+    #[arg(long = "league")]
+    leagues: Vec<String>,
+
+    /// Drop cached data first so everything is fetched from upstream
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print the effective configuration and where each value came from
+    Show {
+        /// Output format
+        #[arg(long, value_enum, default_value = "table")]
+        format: OutputFormat,
+    },
+}
+
+/// One row of the `gems` command output.
+#[derive(Debug, Serialize)]
+struct GemRow {
+    name: String,
+    color: Option<&'static str>,
+    gem_level: u32,
+    gem_quality: u32,
+    corrupted: bool,
+    chaos_value: Option<f64>,
+    listing_count: Option<u32>,
+}
+
+pub async fn calculate(state: &AppState, args: CalculateArgs) -> Result<()> {
+    let calculation = run_calculation(state, calculation_query(&args))
+        .await
+        .map_err(|status| anyhow!("Failed to calculate: {}", status))?;
+    let result = &calculation.response;
+
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(result)?),
+        OutputFormat::Table => {
+            print_calculation(&calculation.league, &args, calculation.options.ignore_after_chaos, result)
+        }
+    }
+
+    Ok(())
+}
+
+/// The `/api/v1/calculate` query for the arguments, so the CLI gets the same
+/// options, links and conversion as the API.
+fn calculation_query(args: &CalculateArgs) -> CalculationQuery {
+    CalculationQuery {
+        league: args.league.clone(),
+        ignore_after_chaos: args.ignore_after_chaos,
+        gem_level: Some(args.gem_level),
+        gem_quality: Some(args.gem_quality),
+        currency: args.currency.clone(),
+        exclude: (!args.exclude.is_empty()).then(|| args.exclude.join(",")),
+        prices: (!args.price.is_empty()).then(|| {
+            args.price
+                .iter()
+                .map(|(name, chaos)| format!("{}:{}", name, chaos))
+                .collect::<Vec<_>>()
+                .join(",")
+        }),
+        offer_size: None,
+        outliers: args.outliers,
+        // --projection-hours implies --trend, so only an explicit --trend turns it on
+        trend: args.trend.then_some(true),
+        projection_hours: args.projection_hours,
+    }
+}
+
+pub async fn gems(state: &AppState, args: GemsArgs) -> Result<()> {
+    let league = args.league.as_deref().unwrap_or(&state.config.defaults.league);
+    let league = resolve_league(state, league).await;
+    let skill_gems = fetch_skill_gems(state, &league)
+        .await
+        .map_err(|status| anyhow!("Failed to load skill gems for {}: {}", league, status))?;
+
+    let name_filter = args.name.as_ref().map(|name| name.to_lowercase());
+    let mut rows: Vec<GemRow> = skill_gems
+        .lines
+        .iter()
+        .filter(|gem| !args.transfigured || is_transfigured_gem(&gem.name))
+        .filter(|gem| {
+            name_filter
+                .as_ref()
+                .is_none_or(|name| gem.name.to_lowercase().contains(name))
+        })
+        .map(to_gem_row)
+        .filter(|row| args.color.is_none() || row.color == args.color.as_deref())
+        .filter(|row| args.gem_level.is_none_or(|level| row.gem_level == level))
+        .filter(|row| args.gem_quality.is_none_or(|quality| row.gem_quality == quality))
+        .filter(|row| args.min_chaos.is_none_or(|min| row.chaos_value.unwrap_or(0.0) >= min))
+        .collect();
+
+    rows.sort_by(|a, b| {
+        b.chaos_value
+            .unwrap_or(0.0)
+            .partial_cmp(&a.chaos_value.unwrap_or(0.0))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&rows)?),
+        OutputFormat::Table => {
+            let table: Vec<Vec<String>> = rows
+                .iter()
+                .map(|row| {
+                    vec![
+                        row.name.clone(),
+                        row.color.unwrap_or("-").to_string(),
+                        row.gem_level.to_string(),
+                        row.gem_quality.to_string(),
+                        if row.corrupted { "yes" } else { "no" }.to_string(),
+                        format_chaos(row.chaos_value),
+                        row.listing_count.map_or("-".to_string(), |count| count.to_string()),
+                    ]
+                })
+                .collect();
+            print_table(&["Gem", "Color", "Level", "Quality", "Corrupted", "Chaos", "Listings"], &table);
+            println!("\n{} gems in {}", rows.len(), league);
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn leagues(state: &AppState, args: LeaguesArgs) -> Result<()> {
+    let realm = args.realm.to_lowercase();
+    if !REALMS.contains(&realm.as_str()) {
+        bail!("Unknown realm '{}', expected one of: {}", args.realm, REALMS.join(", "));
+    }
+
+    let response = fetch_leagues(state, &realm).await;
+
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&response)?),
+        OutputFormat::Table => {
+            let table: Vec<Vec<String>> = response
+                .leagues
+                .iter()
+                .map(|league| {
+                    let kind = match (league.private, league.challenge) {
+                        (true, _) => "private",
+                        (false, true) => "challenge",
+                        (false, false) => "permanent",
+                    };
+                    vec![
+                        league.name.clone(),
+                        kind.to_string(),
+                        if league.hardcore { "yes" } else { "no" }.to_string(),
+                        league.start_at.map_or("-".to_string(), |start| start.format("%Y-%m-%d").to_string()),
+                        league.end_at.map_or("-".to_string(), |end| end.format("%Y-%m-%d").to_string()),
+                    ]
+                })
+                .collect();
+            print_table(&["League", "Type", "Hardcore", "Start", "End"], &table);
+        }
+    }
+
+    Ok(())
+}
+
+/// Fills the cache with league and skill gem data, the same files the server reads.
+pub async fn fetch(state: &AppState, args: FetchArgs) -> Result<()> {
+    if args.force {
+        let cleared = state.cache.clear().await?;
+        println!("Cleared {} cache entries", cleared);
+    }
+
+    let leagues = if args.leagues.is_empty() {
+        fetch_leagues(state, "pc")
+            .await
+            .leagues
+            .into_iter()
+            .map(|league| league.name)
+            .collect()
+    } else {
+        let mut resolved = Vec::new();
+        for league in &args.leagues {
+            resolved.push(resolve_league(state, league).await);
+        }
+        resolved
+    };
+
+    let mut failures = 0;
+    for league in &leagues {
+        match fetch_skill_gems(state, league).await {
+            Ok(gems) => println!("{}: {} gems cached", league, gems.lines.len()),
+            Err(status) => {
+                failures += 1;
+                eprintln!("{}: failed ({})", league, status);
+            }
+        }
+    }
+
+    if failures > 0 {
+        bail!("{} of {} leagues could not be fetched", failures, leagues.len());
+    }
+
+    Ok(())
+}
+
+pub fn config(loaded: &LoadedConfig, command: ConfigCommand) -> Result<()> {
+    match command {
+        ConfigCommand::Show { format } => {
+            let entries = loaded.entries()?;
+            match format {
+                OutputFormat::Json => {
+                    let json: serde_json::Map<String, serde_json::Value> = entries
+                        .into_iter()
+                        .map(|(key, value, source)| {
+                            (key, serde_json::json!({ "value": value, "source": source.to_string() }))
+                        })
+                        .collect();
+                    println!("{}", serde_json::to_string_pretty(&json)?);
+                }
+                OutputFormat::Table => {
+                    let table: Vec<Vec<String>> = entries
+                        .into_iter()
+                        .map(|(key, value, source)| vec![key, value, source.to_string()])
+                        .collect();
+                    print_table(&["Key", "Value", "Source"], &table);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn to_gem_row(gem: &SkillGem) -> GemRow {
+    GemRow {
+        name: gem.name.clone(),
+        color: gem
+            .icon
+            .as_deref()
+            .and_then(GemColor::from_icon_url)
+            .map(|color| color.as_str()),
+        gem_level: gem.gem_level.unwrap_or(1),
+        gem_quality: gem.gem_quality.unwrap_or(0),
+        corrupted: gem.corrupted.unwrap_or(false),
+        chaos_value: gem.chaos_value,
+        listing_count: gem.listing_count,
+    }
+}
+
+fn print_calculation(league: &str, args: &CalculateArgs, ignore_after_chaos: f64, result: &CalculationResponse) {
+    println!(
+        "League: {}  Level: {}  Quality: {}  Ignore below: {}c\n",
+        league, args.gem_level, args.gem_quality, ignore_after_chaos
+    );
+
+    let colors: [(&str, f64, &[GemValue]); 3] = [
+        ("red", result.red_roi, &result.red_gems),
+        ("green", result.green_roi, &result.green_gems),
+        ("blue", result.blue_roi, &result.blue_gems),
+    ];
+
+    match &result.projected {
+        Some(projected) => {
+            let projected_rois = [projected.red_roi, projected.green_roi, projected.blue_roi];
+            let table: Vec<Vec<String>> = colors
+                .iter()
+                .zip(projected_rois)
+                .map(|((color, roi, gems), projected_roi)| {
+                    vec![
+                        color.to_string(),
+                        format!("{:.2}", roi),
+                        format!("{:.2}", projected_roi),
+                        gems.len().to_string(),
+                    ]
+                })
+                .collect();
+            let projected_header = format!("EV in {}h", projected.hours);
+            print_table(&["Color", "EV (chaos)", &projected_header, "Gems"], &table);
+        }
+        None => {
+            let table: Vec<Vec<String>> = colors
+                .iter()
+                .map(|(color, roi, gems)| vec![color.to_string(), format!("{:.2}", roi), gems.len().to_string()])
+                .collect();
+            print_table(&["Color", "EV (chaos)", "Gems"], &table);
+        }
+    }
+
+    if let Some((best, roi, _)) = colors
+        .iter()
+        .filter(|(_, roi, _)| *roi > 0.0)
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+    {
+        println!("\nBest: {} ({:.2} chaos)", best, roi);
+    }
+    if let Some(converted) = &result.converted {
+        println!(
+            "In {} ({:.1}c each) - Red: {:.3}, Green: {:.3}, Blue: {:.3}",
+            converted.currency, converted.chaos_equivalent, converted.red_roi, converted.green_roi, converted.blue_roi
+        );
+    }
+
+    if !result.excluded.is_empty() {
+        println!("\nExcluded: {}", result.excluded.join(", "));
+    }
+    if !result.outliers.is_empty() {
+        println!("\nOutliers:");
+        let table: Vec<Vec<String>> = result
+            .outliers
+            .iter()
+            .map(|gem| {
+                vec![
+                    gem.name.clone(),
+                    format_chaos(gem.chaos_value),
+                    format!("{:.1}", gem.listed_chaos_value),
+                    gem.reasons.join(", "),
+                ]
+            })
+            .collect();
+        print_table(&["Gem", "Chaos", "Listed", "Reasons"], &table);
+    }
+    if let Some(projected) = result.projected.as_ref().filter(|projected| !projected.declining.is_empty()) {
+        println!("\nDeclining:");
+        let table: Vec<Vec<String>> = projected
+            .declining
+            .iter()
+            .map(|gem| {
+                vec![
+                    gem.name.clone(),
+                    gem.color.clone(),
+                    format!("{:.1}", gem.chaos_value),
+                    format!("{:.1}", gem.projected_chaos_value),
+                    format!("{:.1}%", gem.change_percent),
+                ]
+            })
+            .collect();
+        print_table(&["Gem", "Color", "Chaos", "Projected", "7 days"], &table);
+    }
+    if !result.overridden.is_empty() {
+        println!("\nOverridden prices:");
+        let table: Vec<Vec<String>> = result
+            .overridden
+            .iter()
+            .map(|gem| {
+                vec![
+                    gem.name.clone(),
+                    format!("{:.1}", gem.chaos_value),
+                    format!("{:.1}", gem.listed_chaos_value),
+                ]
+            })
+            .collect();
+        print_table(&["Gem", "Chaos", "Listed"], &table);
+    }
+
+    if args.details {
+        for (color, _, gems) in &colors {
+            println!("\n{} gems:", color);
+            let table: Vec<Vec<String>> = gems
+                .iter()
+                .map(|gem| {
+                    let mut row = vec![
+                        gem.name.clone(),
+                        format!("{:.1}", gem.chaos_value),
+                        format!("{:.2}%", gem.probability * 100.0),
+                    ];
+                    if result.projected.is_some() {
+                        row.push(format_chaos(gem.projected_chaos_value));
+                    }
+                    row
+                })
+                .collect();
+            if result.projected.is_some() {
+                print_table(&["Gem", "Chaos", "Probability", "Projected"], &table);
+            } else {
+                print_table(&["Gem", "Chaos", "Probability"], &table);
+            }
+        }
+    }
+}
+
+fn format_chaos(value: Option<f64>) -> String {
+    value.map_or("-".to_string(), |value| format!("{:.1}", value))
+}
+
+fn parse_projection_hours(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(hours) if hours > 0.0 && hours <= MAX_PROJECTION_HOURS => Ok(hours),
+        _ => Err(format!("expected hours above 0 and at most {}, got {:?}", MAX_PROJECTION_HOURS, value)),
+    }
+}
+
+/// Prints rows as a plain-text table with left-aligned text and right-aligned numbers.
+fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(i, (cell, width))| {
+                if i > 0 && cell.trim_end_matches('%').parse::<f64>().is_ok() {
+                    format!("{:>width$}", cell, width = width)
+                } else {
+                    format!("{:<width$}", cell, width = width)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    println!("{}", format_row(headers.to_vec()));
+    println!("{}", widths.iter().map(|width| "-".repeat(*width)).collect::<Vec<_>>().join("  "));
+    for row in rows {
+        println!("{}", format_row(row.iter().map(String::as_str).collect()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use poe_gem_calculator::engine::{trend::TrendOptions, CalculationOptions};
+
+    #[derive(Parser)]
+    struct TestCli {
+        #[command(flatten)]
+        args: CalculateArgs,
+    }
+
+    fn options(args: &[&str]) -> CalculationOptions {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = AppState::new(temp_dir.path().to_str().unwrap()).unwrap();
+        let cli = TestCli::try_parse_from([&["calculate"], args].concat()).unwrap();
+        calculation_query(&cli.args).options(&state).unwrap()
+    }
+
+    #[test]
+    fn test_calculation_query_trend() {
+        assert!(options(&[]).trend.is_none());
+        assert_eq!(options(&["--trend"]).trend.unwrap().hours, TrendOptions::default().hours);
+        // --projection-hours implies --trend
+        assert_eq!(options(&["--projection-hours", "12"]).trend.unwrap().hours, 12.0);
+    }
+}
@@ -0,0 +1,570 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+use crate::{
+    alerts::MAX_COOLDOWN_MINUTES,
+    api::results::MAX_RETENTION_DAYS,
+    engine::{corruption::CorruptionOdds, outliers::OutlierOptions, trend::{TrendOptions, MAX_PROJECTION_HOURS}},
+    filter::DEFAULT_EXCLUDED_RULES,
+};
+
+/// Config file read when `--config` and `POE_GEM_CONFIG` are not set, if it exists.
+pub const DEFAULT_CONFIG_FILE: &str = "poe-gem-calculator.toml";
+
+/// Prefix of the environment variables that override config values. A key such as
+/// `cache.ttl_minutes` is overridden by `POE_GEM_CACHE_TTL_MINUTES`.
+pub const ENV_PREFIX: &str = "POE_GEM_";
+
+/// Effective configuration, merged from defaults, the config file, environment
+/// variables and command-line flags, in that order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub log_level: String,
+    pub server: ServerConfig,
+    pub cache: CacheConfig,
+    pub http: HttpConfig,
+    pub upstream: UpstreamConfig,
+    pub defaults: DefaultsConfig,
+    pub leagues: LeaguesConfig,
+    pub rate_limit: RateLimitConfig,
+    /// Vaal Orb outcome probabilities for the corruption calculator
+    pub corruption: CorruptionOdds,
+    /// Detection and handling of price-fixed listings in expected value calculations
+    pub outliers: OutlierOptions,
+    /// Projection of prices from their 7-day trend, for calculations that ask for it
+    pub trend: TrendOptions,
+    pub results: ResultsConfig,
+    pub alerts: AlertsConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    /// Directory to serve the frontend from instead of the copy embedded in the
+    /// binary; empty for the embedded one
+    pub static_dir: String,
+    /// How long in-flight requests get to finish after SIGINT/SIGTERM
+    pub shutdown_timeout_seconds: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CacheConfig {
+    pub dir: String,
+    /// How long fetched upstream data is served from the cache
+    pub ttl_minutes: i64,
+    /// How long a league poe.ninja has no data for is answered with 404 without asking again
+    pub unknown_league_ttl_minutes: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpConfig {
+    pub timeout_seconds: u64,
+    pub user_agent: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpstreamConfig {
+    pub poe_ninja_url: String,
+    pub poe_api_url: String,
+    /// pathofexile.com trade search page that result links point to
+    pub trade_url: String,
+}
+
+/// Values used when a request doesn't specify them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DefaultsConfig {
+    pub league: String,
+    pub ignore_after_chaos: f64,
+    /// Vaal Orb price counted against corruption profits when poe.ninja has none
+    pub vaal_orb_chaos: f64,
+    /// Gemcutter's Prism price counted against leveling plan profits when poe.ninja has none
+    pub gemcutter_chaos: f64,
+    /// Time or opportunity cost of gaining one gem level, counted against leveling plan profits
+    pub level_cost_chaos: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LeaguesConfig {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+    pub exclude_rules: Vec<String>,
+    pub include_events: bool,
+    pub private: Vec<String>,
+}
+
+/// Calculations saved under a permalink, stored in `results/` in the cache directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResultsConfig {
+    /// How long a saved calculation and its price snapshot are kept
+    pub retention_days: i64,
+}
+
+/// Delivery of alert rules, which are stored in `alerts/` in the cache directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertsConfig {
+    /// Webhooks every firing alert is POSTed to
+    pub webhook_urls: Vec<String>,
+    /// Further attempts after a delivery fails
+    pub retries: u32,
+    /// Wait before the first retry, doubled for every further one
+    pub retry_delay_seconds: u64,
+    /// How long a rule that doesn't set a cooldown stays quiet after firing
+    pub cooldown_minutes: i64,
+    /// How often leagues with alert rules are checked for expired prices to refresh
+    pub refresh_minutes: u64,
+    /// Deliveries kept in the delivery log
+    pub delivery_log_size: usize,
+    /// Rules that can be created through the API, which is not authenticated
+    pub max_rules: usize,
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self {
+            webhook_urls: Vec::new(),
+            retries: 3,
+            retry_delay_seconds: 5,
+            cooldown_minutes: 60,
+            refresh_minutes: 15,
+            delivery_log_size: 200,
+            max_rules: 100,
+        }
+    }
+}
+
+/// Request budgets. A per-minute rate of 0 turns that limit off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// API requests per minute per client
+    pub requests_per_minute: u32,
+    /// API requests a client can make in a burst before the per-minute rate applies
+    pub burst: u32,
+    /// Requests to poe.ninja and the official API per minute, across all clients
+    pub upstream_requests_per_minute: u32,
+    /// Identify clients by `X-Forwarded-For` (only behind a proxy that sets it)
+    pub trust_forwarded_for: bool,
+    /// Proxies in front of the server that append to `X-Forwarded-For`; the client is
+    /// the entry this many from the right
+    pub trusted_proxies: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            log_level: "info".to_string(),
+            server: ServerConfig {
+                host: "0.0.0.0".to_string(),
+                port: 3000,
+                static_dir: String::new(),
+                shutdown_timeout_seconds: 30,
+            },
+            cache: CacheConfig {
+                dir: "cache".to_string(),
+                ttl_minutes: 60,
+                unknown_league_ttl_minutes: 10,
+            },
+            http: HttpConfig {
+                timeout_seconds: 30,
+                user_agent: format!("poe-gem-calculator/{}", env!("CARGO_PKG_VERSION")),
+            },
+            upstream: UpstreamConfig {
+                poe_ninja_url: "https://poe.ninja".to_string(),
+                poe_api_url: "https://api.pathofexile.com".to_string(),
+                trade_url: "https://www.pathofexile.com/trade/search".to_string(),
+            },
+            defaults: DefaultsConfig {
+                league: "Standard".to_string(),
+                ignore_after_chaos: 5.0,
+                vaal_orb_chaos: 1.0,
+                gemcutter_chaos: 1.0,
+                level_cost_chaos: 0.0,
+            },
+            leagues: LeaguesConfig {
+                allow: Vec::new(),
+                deny: Vec::new(),
+                exclude_rules: DEFAULT_EXCLUDED_RULES.iter().map(|rule| rule.to_string()).collect(),
+                include_events: false,
+                private: Vec::new(),
+            },
+            rate_limit: RateLimitConfig {
+                requests_per_minute: 60,
+                burst: 20,
+                upstream_requests_per_minute: 30,
+                trust_forwarded_for: false,
+                trusted_proxies: 1,
+            },
+            corruption: CorruptionOdds::default(),
+            outliers: OutlierOptions::default(),
+            trend: TrendOptions::default(),
+            results: ResultsConfig { retention_days: 30 },
+            alerts: AlertsConfig::default(),
+        }
+    }
+}
+
+/// Where an effective config value came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase", tag = "type", content = "name")]
+pub enum ConfigSource {
+    Default,
+    File(PathBuf),
+    Env(String),
+    Cli,
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "default"),
+            ConfigSource::File(path) => write!(f, "file {}", path.display()),
+            ConfigSource::Env(name) => write!(f, "env {}", name),
+            ConfigSource::Cli => write!(f, "cli"),
+        }
+    }
+}
+
+/// The effective config together with the source of every value.
+#[derive(Debug, Clone)]
+pub struct LoadedConfig {
+    pub config: Config,
+    /// Dotted key (e.g. `server.port`) to the layer that set it
+    pub sources: BTreeMap<String, ConfigSource>,
+}
+
+impl LoadedConfig {
+    /// Every leaf value as a dotted key, its display value and its source.
+    pub fn entries(&self) -> Result<Vec<(String, String, ConfigSource)>> {
+        let table = Table::try_from(&self.config)?;
+        Ok(flatten(&table)
+            .into_iter()
+            .map(|(key, value)| {
+                let source = self.sources.get(&key).cloned().unwrap_or(ConfigSource::Default);
+                (key, display_value(&value), source)
+            })
+            .collect())
+    }
+}
+
+/// Builds the effective config.
+///
+/// `config_path` is the file given on the command line; otherwise `POE_GEM_CONFIG`
+/// or `poe-gem-calculator.toml` in the working directory are used if present.
+/// `cli` holds the values of flags that were passed explicitly, by dotted key.
+pub fn load(config_path: Option<&Path>, cli: Table) -> Result<LoadedConfig> {
+    let env: BTreeMap<String, String> = std::env::vars().collect();
+    let file = match config_path {
+        Some(path) => Some(path.to_path_buf()),
+        None => env
+            .get("POE_GEM_CONFIG")
+            .map(PathBuf::from)
+            .or_else(|| Path::new(DEFAULT_CONFIG_FILE).exists().then(|| PathBuf::from(DEFAULT_CONFIG_FILE))),
+    };
+
+    let file_layer = match &file {
+        Some(path) => {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read config file: {:?}", path))?;
+            let table: Table = toml::from_str(&content)
+                .with_context(|| format!("Failed to parse config file: {:?}", path))?;
+            Some((table, path.clone()))
+        }
+        None => None,
+    };
+
+    resolve(file_layer, &env, cli)
+}
+
+/// Merges the layers and validates the result. Split from `load` so tests don't
+/// depend on the process environment.
+fn resolve(
+    file: Option<(Table, PathBuf)>,
+    env: &BTreeMap<String, String>,
+    cli: Table,
+) -> Result<LoadedConfig> {
+    let mut merged = Table::try_from(Config::default())?;
+    let defaults = flatten(&merged);
+    let mut sources = BTreeMap::new();
+
+    if let Some((table, path)) = file {
+        for (key, value) in flatten(&table) {
+            set_path(&mut merged, &key, value);
+            sources.insert(key, ConfigSource::File(path.clone()));
+        }
+    }
+
+    for (key, default) in &defaults {
+        let name = env_var_name(key);
+        if let Some(raw) = env.get(&name) {
+            let value = parse_env_value(raw, default)
+                .with_context(|| format!("Invalid value for {}: {:?}", name, raw))?;
+            set_path(&mut merged, key, value);
+            sources.insert(key.clone(), ConfigSource::Env(name));
+        }
+    }
+
+    for (key, value) in flatten(&cli) {
+        set_path(&mut merged, &key, value);
+        sources.insert(key, ConfigSource::Cli);
+    }
+
+    let config: Config = Value::Table(merged)
+        .try_into()
+        .context("Invalid configuration")?;
+    validate(&config)?;
+
+    Ok(LoadedConfig { config, sources })
+}
+
+/// Checks values that deserialize fine but can't work.
+fn validate(config: &Config) -> Result<()> {
+    let mut problems = Vec::new();
+
+    if !["trace", "debug", "info", "warn", "error"].contains(&config.log_level.to_lowercase().as_str()) {
+        problems.push(format!("log_level must be one of trace, debug, info, warn, error (got {:?})", config.log_level));
+    }
+    if config.server.port == 0 {
+        problems.push("server.port must not be 0".to_string());
+    }
+    if config.server.shutdown_timeout_seconds == 0 {
+        problems.push("server.shutdown_timeout_seconds must be positive".to_string());
+    }
+    if config.cache.dir.trim().is_empty() {
+        problems.push("cache.dir must not be empty".to_string());
+    }
+    if config.cache.ttl_minutes <= 0 {
+        problems.push("cache.ttl_minutes must be positive".to_string());
+    }
+    if config.cache.unknown_league_ttl_minutes <= 0 {
+        problems.push("cache.unknown_league_ttl_minutes must be positive".to_string());
+    }
+    if config.rate_limit.requests_per_minute > 0 && config.rate_limit.burst == 0 {
+        problems.push("rate_limit.burst must be positive when rate_limit.requests_per_minute is set".to_string());
+    }
+    if config.rate_limit.trust_forwarded_for && config.rate_limit.trusted_proxies == 0 {
+        problems.push("rate_limit.trusted_proxies must be positive when rate_limit.trust_forwarded_for is set".to_string());
+    }
+    if config.http.timeout_seconds == 0 {
+        problems.push("http.timeout_seconds must be positive".to_string());
+    }
+    if config.http.user_agent.trim().is_empty() {
+        problems.push("http.user_agent must not be empty".to_string());
+    }
+    for (key, url) in [
+        ("upstream.poe_ninja_url", &config.upstream.poe_ninja_url),
+        ("upstream.poe_api_url", &config.upstream.poe_api_url),
+        ("upstream.trade_url", &config.upstream.trade_url),
+    ] {
+        if reqwest::Url::parse(url).is_err() {
+            problems.push(format!("{} is not a valid URL: {:?}", key, url));
+        }
+    }
+    if config.defaults.league.trim().is_empty() {
+        problems.push("defaults.league must not be empty".to_string());
+    }
+    if config.defaults.ignore_after_chaos.is_nan() || config.defaults.ignore_after_chaos < 0.0 {
+        problems.push("defaults.ignore_after_chaos must be zero or positive".to_string());
+    }
+
+    for (key, price) in [
+        ("defaults.vaal_orb_chaos", config.defaults.vaal_orb_chaos),
+        ("defaults.gemcutter_chaos", config.defaults.gemcutter_chaos),
+        ("defaults.level_cost_chaos", config.defaults.level_cost_chaos),
+    ] {
+        if price.is_nan() || price < 0.0 {
+            problems.push(format!("{} must be zero or positive", key));
+        }
+    }
+    let odds = &config.corruption;
+    if [odds.no_change, odds.level_up, odds.level_down, odds.quality_up, odds.quality_down, odds.vaal]
+        .iter()
+        .any(|probability| probability.is_nan() || *probability < 0.0)
+    {
+        problems.push("corruption probabilities must be zero or positive".to_string());
+    } else if (odds.total() - 1.0).abs() > 1e-6 {
+        problems.push(format!("corruption probabilities must add up to 1 (got {})", odds.total()));
+    }
+
+    let outliers = &config.outliers;
+    if outliers.max_spike_percent.is_nan() || outliers.max_spike_percent < 0.0 {
+        problems.push("outliers.max_spike_percent must be zero or positive".to_string());
+    }
+    if outliers.max_variant_ratio.is_nan() || outliers.max_variant_ratio < 1.0 {
+        problems.push("outliers.max_variant_ratio must be at least 1".to_string());
+    }
+    if outliers.fence_iqr.is_nan() || outliers.fence_iqr < 0.0 {
+        problems.push("outliers.fence_iqr must be zero or positive".to_string());
+    }
+
+    let trend = &config.trend;
+    if !(trend.hours > 0.0 && trend.hours <= MAX_PROJECTION_HOURS) {
+        problems.push(format!("trend.hours must be above 0 and at most {}", MAX_PROJECTION_HOURS));
+    }
+    if trend.max_change_percent.is_nan() || trend.max_change_percent < 0.0 {
+        problems.push("trend.max_change_percent must be zero or positive".to_string());
+    }
+    if !(trend.decline_percent > 0.0 && trend.decline_percent <= 100.0) {
+        problems.push("trend.decline_percent must be above 0 and at most 100".to_string());
+    }
+
+    if !(1..=MAX_RETENTION_DAYS).contains(&config.results.retention_days) {
+        problems.push(format!("results.retention_days must be positive and at most {}", MAX_RETENTION_DAYS));
+    }
+
+    let alerts = &config.alerts;
+    for url in &alerts.webhook_urls {
+        if !reqwest::Url::parse(url).is_ok_and(|parsed| ["http", "https"].contains(&parsed.scheme())) {
+            problems.push(format!("alerts.webhook_urls must be http or https URLs (got {:?})", url));
+        }
+    }
+    if !(0..=MAX_COOLDOWN_MINUTES).contains(&alerts.cooldown_minutes) {
+        problems.push(format!("alerts.cooldown_minutes must be between 0 and {}", MAX_COOLDOWN_MINUTES));
+    }
+    if alerts.refresh_minutes == 0 {
+        problems.push("alerts.refresh_minutes must be positive".to_string());
+    }
+    if alerts.delivery_log_size == 0 {
+        problems.push("alerts.delivery_log_size must be positive".to_string());
+    }
+    if alerts.max_rules == 0 {
+        problems.push("alerts.max_rules must be positive".to_string());
+    }
+
+    if !problems.is_empty() {
+        bail!("Invalid configuration:\n  {}", problems.join("\n  "));
+    }
+    Ok(())
+}
+
+pub fn env_var_name(key: &str) -> String {
+    format!("{}{}", ENV_PREFIX, key.replace('.', "_").to_uppercase())
+}
+
+/// Parses an environment variable into the same TOML type as the default value.
+/// Lists are comma separated.
+fn parse_env_value(raw: &str, default: &Value) -> Result<Value> {
+    Ok(match default {
+        Value::Integer(_) => Value::Integer(raw.trim().parse()?),
+        Value::Float(_) => Value::Float(raw.trim().parse()?),
+        Value::Boolean(_) => Value::Boolean(raw.trim().parse()?),
+        Value::Array(_) => Value::Array(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| Value::String(item.to_string()))
+                .collect(),
+        ),
+        _ => Value::String(raw.to_string()),
+    })
+}
+
+/// Flattens nested tables into dotted keys. Arrays are leaves.
+fn flatten(table: &Table) -> Vec<(String, Value)> {
+    let mut leaves = Vec::new();
+    for (key, value) in table {
+        match value {
+            Value::Table(nested) => {
+                for (nested_key, nested_value) in flatten(nested) {
+                    leaves.push((format!("{}.{}", key, nested_key), nested_value));
+                }
+            }
+            _ => leaves.push((key.clone(), value.clone())),
+        }
+    }
+    leaves
+}
+
+fn set_path(table: &mut Table, key: &str, value: Value) {
+    match key.split_once('.') {
+        Some((section, rest)) => {
+            let nested = table
+                .entry(section.to_string())
+                .or_insert_with(|| Value::Table(Table::new()));
+            if let Value::Table(nested) = nested {
+                set_path(nested, rest, value);
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+        }
+    }
+}
+
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(items) => items.iter().map(display_value).collect::<Vec<_>>().join(","),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layers_and_sources() {
+        let file: Table = toml::from_str(
+            r#"
+            [server]
+            port = 8080
+
+            [cache]
+            ttl_minutes = 30
+            "#,
+        )
+        .unwrap();
+        let env = BTreeMap::from([
+            ("POE_GEM_CACHE_TTL_MINUTES".to_string(), "15".to_string()),
+            ("POE_GEM_LEAGUES_DENY".to_string(), "Settlers, Hardcore Settlers".to_string()),
+        ]);
+        let mut cli = Table::new();
+        set_path(&mut cli, "server.port", Value::Integer(9000));
+
+        let loaded = resolve(Some((file, PathBuf::from("test.toml"))), &env, cli).unwrap();
+
+        assert_eq!(loaded.config.server.port, 9000);
+        assert_eq!(loaded.config.cache.ttl_minutes, 15);
+        assert_eq!(loaded.config.leagues.deny, vec!["Settlers", "Hardcore Settlers"]);
+        assert_eq!(loaded.config.defaults.league, "Standard");
+
+        assert_eq!(loaded.sources["server.port"], ConfigSource::Cli);
+        assert_eq!(loaded.sources["cache.ttl_minutes"], ConfigSource::Env("POE_GEM_CACHE_TTL_MINUTES".to_string()));
+        let entries = loaded.entries().unwrap();
+        let league = entries.iter().find(|(key, _, _)| key == "defaults.league").unwrap();
+        assert_eq!(league.2, ConfigSource::Default);
+    }
+
+    #[test]
+    fn test_validation_rejects_bad_values() {
+        let file: Table = toml::from_str(
+            "[cache]\nttl_minutes = 0\n[upstream]\npoe_ninja_url = \"not a url\"\n[results]\nretention_days = 9223372036854775807",
+        )
+        .unwrap();
+        let error = resolve(Some((file, PathBuf::from("test.toml"))), &BTreeMap::new(), Table::new()).unwrap_err();
+        let message = format!("{:#}", error);
+        assert!(message.contains("cache.ttl_minutes"));
+        assert!(message.contains("upstream.poe_ninja_url"));
+        assert!(message.contains("results.retention_days"));
+
+        let unknown: Table = toml::from_str("[cache]\nttl = 5").unwrap();
+        assert!(resolve(Some((unknown, PathBuf::from("test.toml"))), &BTreeMap::new(), Table::new()).is_err());
+
+        let env = BTreeMap::from([("POE_GEM_SERVER_PORT".to_string(), "eighty".to_string())]);
+        assert!(resolve(None, &env, Table::new()).is_err());
+    }
+}
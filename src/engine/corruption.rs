@@ -0,0 +1,187 @@
+//! Expected value of corrupting a 20/20 transfigured gem with a Vaal Orb.
+//!
+//! Each outcome is priced with the matching corrupted listing on poe.ninja. Outcomes
+//! poe.ninja doesn't list (19/20, quality below 20, Vaal versions of transfigured
+//! gems) are valued like a corrupted gem that didn't change.
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{is_transfigured_gem, CorruptionOutcomeValue, CorruptionValue, GemColor, SkillGem, SkillGemResponse};
+
+/// Level and quality of the gems being corrupted.
+const BASE_LEVEL: u32 = 20;
+const BASE_QUALITY: u32 = 20;
+
+/// Quality of the corrupted gems poe.ninja lists above 20.
+const CORRUPTED_QUALITY: u32 = 23;
+
+/// Chance of each Vaal Orb outcome on a gem. They should add up to 1.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CorruptionOdds {
+    /// Corrupted, nothing else changes
+    pub no_change: f64,
+    /// Gem level goes up by one (20 to 21)
+    pub level_up: f64,
+    /// Gem level goes down by one (20 to 19)
+    pub level_down: f64,
+    /// Quality goes up (20 to 23)
+    pub quality_up: f64,
+    /// Quality goes down
+    pub quality_down: f64,
+    /// Turns into the Vaal version of the gem, if it has one
+    pub vaal: f64,
+}
+
+impl Default for CorruptionOdds {
+    fn default() -> Self {
+        Self {
+            no_change: 0.25,
+            level_up: 0.125,
+            level_down: 0.125,
+            quality_up: 0.125,
+            quality_down: 0.125,
+            vaal: 0.25,
+        }
+    }
+}
+
+impl CorruptionOdds {
+    pub fn total(&self) -> f64 {
+        self.no_change + self.level_up + self.level_down + self.quality_up + self.quality_down + self.vaal
+    }
+}
+
+/// Prices corrupting each 20/20 transfigured gem, most profitable first.
+///
+/// Profit is the expected value after corruption minus the price of the uncorrupted
+/// 20/20 gem and `vaal_orb_chaos`.
+pub fn corruption_values(
+    skill_gems_response: &SkillGemResponse,
+    odds: &CorruptionOdds,
+    vaal_orb_chaos: f64,
+) -> Vec<CorruptionValue> {
+    let price = |name: &str, level: u32, quality: u32, corrupted: bool| {
+        find_listing(skill_gems_response, name, level, quality, corrupted).and_then(|gem| gem.chaos_value)
+    };
+
+    let mut values: Vec<CorruptionValue> = skill_gems_response
+        .lines
+        .iter()
+        .filter(|gem| gem.trade_filter.is_some() && is_transfigured_gem(&gem.name))
+        .filter(|gem| is_listing(gem, BASE_LEVEL, BASE_QUALITY, false))
+        .map(|base| {
+            let name = &base.name;
+            let base_chaos_value = base.chaos_value.unwrap_or(0.0);
+            let unchanged = price(name, BASE_LEVEL, BASE_QUALITY, true);
+
+            let outcomes: Vec<CorruptionOutcomeValue> = [
+                ("no_change", odds.no_change, unchanged),
+                ("level_up", odds.level_up, price(name, BASE_LEVEL + 1, BASE_QUALITY, true)),
+                ("level_down", odds.level_down, price(name, BASE_LEVEL - 1, BASE_QUALITY, true)),
+                ("quality_up", odds.quality_up, price(name, BASE_LEVEL, CORRUPTED_QUALITY, true)),
+                ("quality_down", odds.quality_down, None),
+                ("vaal", odds.vaal, price(&format!("Vaal {}", name), BASE_LEVEL, BASE_QUALITY, true)),
+            ]
+            .into_iter()
+            .map(|(outcome, probability, listed_price)| CorruptionOutcomeValue {
+                outcome: outcome.to_string(),
+                probability,
+                chaos_value: listed_price.or(unchanged).unwrap_or(0.0),
+                listed: listed_price.is_some(),
+            })
+            .collect();
+
+            let expected_value = outcomes
+                .iter()
+                .fold(0.0, |total, outcome| total + outcome.probability * outcome.chaos_value);
+
+            CorruptionValue {
+                name: name.clone(),
+                color: base
+                    .icon
+                    .as_deref()
+                    .and_then(GemColor::from_icon_url)
+                    .map(|color| color.as_str().to_string()),
+                base_chaos_value,
+                expected_value,
+                profit: expected_value - base_chaos_value - vaal_orb_chaos,
+                outcomes,
+            }
+        })
+        .collect();
+
+    values.sort_by(|a, b| b.profit.partial_cmp(&a.profit).unwrap_or(std::cmp::Ordering::Equal));
+    values
+}
+
+fn find_listing<'a>(
+    skill_gems_response: &'a SkillGemResponse,
+    name: &str,
+    gem_level: u32,
+    gem_quality: u32,
+    corrupted: bool,
+) -> Option<&'a SkillGem> {
+    skill_gems_response
+        .lines
+        .iter()
+        .find(|gem| gem.name == name && is_listing(gem, gem_level, gem_quality, corrupted))
+}
+
+fn is_listing(gem: &SkillGem, gem_level: u32, gem_quality: u32, corrupted: bool) -> bool {
+    gem.gem_level == Some(gem_level)
+        && gem.gem_quality == Some(gem_quality)
+        && gem.corrupted.unwrap_or(false) == corrupted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing(name: &str, level: u32, quality: u32, corrupted: bool, chaos: f64) -> serde_json::Value {
+        serde_json::json!({
+            "name": name,
+            "gemLevel": level,
+            "gemQuality": quality,
+            "corrupted": corrupted,
+            "chaosValue": chaos,
+            "tradeFilter": {},
+        })
+    }
+
+    #[test]
+    fn test_corruption_values() {
+        let overview: SkillGemResponse = serde_json::from_value(serde_json::json!({
+            "lines": [
+                listing("Spark of Unpredictability", 20, 20, false, 10.0),
+                listing("Spark of Unpredictability", 20, 20, true, 6.0),
+                listing("Spark of Unpredictability", 21, 20, true, 100.0),
+                listing("Spark of Unpredictability", 20, 23, true, 20.0),
+                listing("Cleave of Rage", 20, 20, false, 50.0),
+                listing("Spark", 20, 20, false, 1.0),
+            ]
+        }))
+        .unwrap();
+
+        let values = corruption_values(&overview, &CorruptionOdds::default(), 1.0);
+        assert_eq!(values.len(), 2);
+
+        let spark = &values[0];
+        assert_eq!(spark.name, "Spark of Unpredictability");
+        // 0.25 * 6 + 0.125 * 100 + 0.125 * 6 + 0.125 * 20 + 0.125 * 6 + 0.25 * 6
+        assert!((spark.expected_value - 19.5).abs() < 1e-9);
+        assert!((spark.profit - 8.5).abs() < 1e-9);
+        let listed: Vec<bool> = spark.outcomes.iter().map(|outcome| outcome.listed).collect();
+        assert_eq!(listed, vec![true, true, false, true, false, false]);
+
+        // No corrupted prices at all: corrupting loses the gem's value
+        let cleave = &values[1];
+        assert_eq!(cleave.expected_value, 0.0);
+        assert!((cleave.profit - -51.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_default_odds_add_up() {
+        assert!((CorruptionOdds::default().total() - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,189 @@
+//! Profit of leveling gems and raising their quality before selling them.
+//!
+//! A 1/0 gem is leveled to 20/0, then Gemcutter's Prisms take it to 20/20. Each
+//! step is priced with the uncorrupted poe.ninja listings on both ends, and the
+//! inputs are one Gemcutter's Prism per quality point plus an optional cost per
+//! level for the time spent leveling.
+
+use std::cmp::Ordering;
+
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::models::{is_transfigured_gem, GemColor, LevelingPlan, SkillGem, SkillGemResponse};
+
+use super::matches_variant;
+
+/// Gem level reached by leveling.
+const MAX_LEVEL: u32 = 20;
+
+/// Quality reached with Gemcutter's Prisms, one prism per point.
+const MAX_QUALITY: u32 = 20;
+
+/// Inputs of a leveling plan beyond the price data itself.
+#[derive(Debug, Clone)]
+pub struct LevelingOptions {
+    /// Price of one Gemcutter's Prism in chaos
+    pub gemcutter_chaos: f64,
+    /// Time or opportunity cost of gaining one gem level, in chaos
+    pub level_cost_chaos: f64,
+    /// Only plan gems with a transfigured name
+    pub transfigured_only: bool,
+}
+
+/// Order of a leveling plan ranking, best first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LevelingSort {
+    /// Chaos gained per gem
+    #[default]
+    Profit,
+    /// Profit relative to what the gem and the inputs cost
+    Roi,
+    /// Listings of the finished gem, so it sells quickly
+    ListingCount,
+}
+
+/// Every level and quality step of every gem with a price on both ends.
+///
+/// Plans only use uncorrupted listings that can be traded. Gems without a price
+/// for the start or the end of a step are left out of that step.
+pub fn leveling_plans(
+    skill_gems_response: &SkillGemResponse,
+    options: &LevelingOptions,
+    sort: LevelingSort,
+) -> Vec<LevelingPlan> {
+    let mut names: Vec<&str> = skill_gems_response
+        .lines
+        .iter()
+        .filter(|gem| gem.trade_filter.is_some())
+        .filter(|gem| !options.transfigured_only || is_transfigured_gem(&gem.name))
+        .map(|gem| gem.name.as_str())
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+
+    let steps = [
+        ("level", (1, 0), (MAX_LEVEL, 0)),
+        ("quality", (MAX_LEVEL, 0), (MAX_LEVEL, MAX_QUALITY)),
+        ("level_and_quality", (1, 0), (MAX_LEVEL, MAX_QUALITY)),
+    ];
+
+    let mut plans: Vec<LevelingPlan> = names
+        .into_iter()
+        .flat_map(|name| {
+            steps.iter().filter_map(move |(step, from, to)| {
+                let buy = find_variant(skill_gems_response, name, from.0, from.1)?;
+                let sell = find_variant(skill_gems_response, name, to.0, to.1)?;
+                let buy_chaos = buy.chaos_value?;
+                let sell_chaos = sell.chaos_value?;
+
+                let levels = to.0 - from.0;
+                let gemcutters = to.1 - from.1;
+                let input_chaos =
+                    levels as f64 * options.level_cost_chaos + gemcutters as f64 * options.gemcutter_chaos;
+                let profit = sell_chaos - buy_chaos - input_chaos;
+                let cost = buy_chaos + input_chaos;
+
+                Some(LevelingPlan {
+                    name: name.to_string(),
+                    color: sell
+                        .icon
+                        .as_deref()
+                        .and_then(GemColor::from_icon_url)
+                        .map(|color| color.as_str().to_string()),
+                    step: step.to_string(),
+                    from: format!("{}/{}", from.0, from.1),
+                    to: format!("{}/{}", to.0, to.1),
+                    buy_chaos,
+                    sell_chaos,
+                    levels,
+                    gemcutters,
+                    input_chaos,
+                    profit,
+                    roi: if cost > 0.0 { profit / cost } else { 0.0 },
+                    listing_count: sell.listing_count,
+                })
+            })
+        })
+        .collect();
+
+    match sort {
+        LevelingSort::Profit => plans.sort_by(|a, b| b.profit.partial_cmp(&a.profit).unwrap_or(Ordering::Equal)),
+        LevelingSort::Roi => plans.sort_by(|a, b| b.roi.partial_cmp(&a.roi).unwrap_or(Ordering::Equal)),
+        LevelingSort::ListingCount => plans.sort_by_key(|plan| std::cmp::Reverse(plan.listing_count.unwrap_or(0))),
+    }
+    plans
+}
+
+fn find_variant<'a>(
+    skill_gems_response: &'a SkillGemResponse,
+    name: &str,
+    gem_level: u32,
+    gem_quality: u32,
+) -> Option<&'a SkillGem> {
+    skill_gems_response
+        .lines
+        .iter()
+        .find(|gem| gem.name == name && gem.trade_filter.is_some() && matches_variant(gem, gem_level, gem_quality))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing(name: &str, level: Option<u32>, quality: Option<u32>, chaos: f64, listings: u32) -> serde_json::Value {
+        serde_json::json!({
+            "name": name,
+            "gemLevel": level,
+            "gemQuality": quality,
+            "chaosValue": chaos,
+            "listingCount": listings,
+            "tradeFilter": {},
+        })
+    }
+
+    #[test]
+    fn test_leveling_plans() {
+        let overview: SkillGemResponse = serde_json::from_value(serde_json::json!({
+            "lines": [
+                listing("Spark of Unpredictability", None, None, 5.0, 10),
+                listing("Spark of Unpredictability", Some(20), None, 30.0, 40),
+                listing("Spark of Unpredictability", Some(20), Some(20), 60.0, 20),
+                listing("Cleave of Rage", Some(1), Some(0), 2.0, 5),
+                listing("Cleave of Rage", Some(20), Some(20), 100.0, 3),
+                listing("Spark", None, None, 1.0, 100),
+                listing("Spark", Some(20), None, 4.0, 100),
+            ]
+        }))
+        .unwrap();
+        let options = LevelingOptions { gemcutter_chaos: 1.0, level_cost_chaos: 0.5, transfigured_only: false };
+
+        let plans = leveling_plans(&overview, &options, LevelingSort::Profit);
+        let steps: Vec<(&str, &str)> = plans.iter().map(|plan| (plan.name.as_str(), plan.step.as_str())).collect();
+        assert_eq!(
+            steps,
+            vec![
+                ("Cleave of Rage", "level_and_quality"),
+                ("Spark of Unpredictability", "level_and_quality"),
+                ("Spark of Unpredictability", "level"),
+                ("Spark of Unpredictability", "quality"),
+                ("Spark", "level"),
+            ]
+        );
+
+        // 100 - 2 - 19 levels * 0.5 - 20 prisms * 1
+        let cleave = &plans[0];
+        assert!((cleave.input_chaos - 29.5).abs() < 1e-9);
+        assert!((cleave.profit - 68.5).abs() < 1e-9);
+        assert!((cleave.roi - 68.5 / 31.5).abs() < 1e-9);
+
+        // 4 - 1 - 9.5 loses money
+        assert!((plans[4].profit - -6.5).abs() < 1e-9);
+
+        let transfigured = LevelingOptions { transfigured_only: true, ..options };
+        let by_listings = leveling_plans(&overview, &transfigured, LevelingSort::ListingCount);
+        assert_eq!(by_listings.len(), 4);
+        assert_eq!(by_listings[0].listing_count, Some(40));
+    }
+}
@@ -0,0 +1,469 @@
+//! Expected value of the Dedication to the Goddess lab font craft.
+//!
+//! The font offers random transfigured gems of the chosen color (three of them) and
+//! the player keeps the best one, so a color's expected value is the price of each
+//! gem weighted by the chance that it is the most valuable of those offered.
+
+pub mod corruption;
+pub mod leveling;
+pub mod outliers;
+pub mod trend;
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use tracing::debug;
+
+use self::outliers::{adjust_outliers, OutlierOptions};
+use self::trend::{project_pool, TrendOptions};
+use crate::models::{
+    is_transfigured_gem, CalculationResponse, GemColor, GemValue, OverriddenGem, ProjectedValues, SkillGem,
+    SkillGemResponse,
+};
+
+/// Number of gems the font offers per craft.
+pub const DEFAULT_OFFER_SIZE: usize = 3;
+
+/// Gem levels poe.ninja lists transfigured gems at.
+pub const GEM_LEVELS: [u32; 3] = [1, 20, 21];
+
+/// Gem qualities poe.ninja lists transfigured gems at.
+pub const GEM_QUALITIES: [u32; 3] = [0, 20, 23];
+
+/// Whether poe.ninja lists transfigured gems at this level and quality.
+pub fn is_listed_variant(gem_level: u32, gem_quality: u32) -> bool {
+    GEM_LEVELS.contains(&gem_level) && GEM_QUALITIES.contains(&gem_quality)
+}
+
+/// Inputs of an expected value calculation beyond the price data itself.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CalculationOptions {
+    /// Gem level to price: 1, 20 or 21
+    pub gem_level: u32,
+    /// Gem quality to price: 0, 20 or 23
+    pub gem_quality: u32,
+    /// Gems worth less than this many chaos count as 0
+    pub ignore_after_chaos: f64,
+    /// Number of gems offered per craft, of which the best is kept
+    pub offer_size: usize,
+    /// Gem names or `*` patterns (case-insensitive) left out of the pool, as if they
+    /// could not be rolled
+    pub excluded: Vec<String>,
+    /// Chaos values used instead of poe.ninja's, by gem name or `*` pattern
+    /// (case-insensitive). When several match a gem the longest one wins
+    pub price_overrides: BTreeMap<String, f64>,
+    /// Detection and handling of price-fixed listings; gems with a price override
+    /// are never outliers
+    pub outliers: OutlierOptions,
+    /// Projection of prices from their 7-day trend, for an expected value next to the
+    /// current one; None to skip it
+    pub trend: Option<TrendOptions>,
+}
+
+impl Default for CalculationOptions {
+    fn default() -> Self {
+        Self {
+            gem_level: 1,
+            gem_quality: 0,
+            ignore_after_chaos: 0.0,
+            offer_size: DEFAULT_OFFER_SIZE,
+            excluded: Vec::new(),
+            price_overrides: BTreeMap::new(),
+            outliers: OutlierOptions::default(),
+            trend: None,
+        }
+    }
+}
+
+/// Whether a listing is the level/quality variant being priced.
+///
+/// Level 1 also matches listings without a level, quality 0 listings without a
+/// quality. Above level 20 or quality 20 only corrupted listings match, otherwise
+/// only uncorrupted ones.
+pub fn matches_variant(gem: &SkillGem, gem_level: u32, gem_quality: u32) -> bool {
+    let matches_level = gem.gem_level == Some(gem_level) || (gem_level == 1 && gem.gem_level.is_none());
+    let matches_quality = if gem_quality == 0 {
+        gem.gem_quality.is_none() || gem.gem_quality == Some(0)
+    } else {
+        gem.gem_quality == Some(gem_quality)
+    };
+    let matches_corruption = if gem_level > 20 || gem_quality > 20 {
+        gem.corrupted == Some(true)
+    } else {
+        gem.corrupted.is_none() || gem.corrupted == Some(false)
+    };
+
+    matches_level && matches_quality && matches_corruption
+}
+
+/// Names and chaos values of the tradeable transfigured gems of one color in the
+/// given variant, most valuable first.
+pub fn transfigured_gems_by_value(
+    skill_gems_response: &SkillGemResponse,
+    color: GemColor,
+    gem_level: u32,
+    gem_quality: u32,
+) -> Vec<(String, f64)> {
+    let mut gems: Vec<(String, f64)> = skill_gems_response
+        .lines
+        .iter()
+        // Must have trade filter to be considered
+        .filter(|gem| gem.trade_filter.is_some() && matches_variant(gem, gem_level, gem_quality))
+        .filter(|gem| is_transfigured_gem(&gem.name))
+        .filter(|gem| gem.icon.as_deref().and_then(GemColor::from_icon_url) == Some(color))
+        .map(|gem| (gem.name.clone(), gem.chaos_value.unwrap_or(0.0)))
+        .collect();
+
+    gems.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    gems
+}
+
+/// Groups the transfigured gems matching the level/quality filter by color and
+/// computes each color's expected value.
+pub fn calculate_expected_values(
+    skill_gems_response: &SkillGemResponse,
+    gem_level: u32,
+    gem_quality: u32,
+    ignore_after_chaos: f64,
+) -> CalculationResponse {
+    let options = CalculationOptions {
+        gem_level,
+        gem_quality,
+        ignore_after_chaos,
+        ..CalculationOptions::default()
+    };
+    calculate_with_options(skill_gems_response, &options)
+}
+
+/// [`calculate_expected_values`] with a custom offer size, excluded gems, price
+/// overrides and outlier handling.
+///
+/// Exclusions, overrides and outliers are applied before the gems are ranked, and
+/// every gem they affected is listed in the response. With `trend` set, the pools are
+/// also projected ahead (overridden gems keep their price) and ranked again for the
+/// projected expected values.
+pub fn calculate_with_options(skill_gems_response: &SkillGemResponse, options: &CalculationOptions) -> CalculationResponse {
+    let mut excluded = Vec::new();
+    let mut overridden = Vec::new();
+    let mut outliers = Vec::new();
+    let mut declining = Vec::new();
+    let mut pool = |color| {
+        let mut gems = Vec::new();
+        let mut overridden_gems = Vec::new();
+        for (name, listed_chaos_value) in
+            transfigured_gems_by_value(skill_gems_response, color, options.gem_level, options.gem_quality)
+        {
+            if options.excluded.iter().any(|pattern| matches_pattern(pattern, &name)) {
+                excluded.push(name);
+                continue;
+            }
+            let price_override = options
+                .price_overrides
+                .iter()
+                .filter(|(pattern, _)| matches_pattern(pattern, &name))
+                .max_by_key(|(pattern, _)| pattern.len());
+            match price_override {
+                Some((_, chaos_value)) => {
+                    overridden.push(OverriddenGem {
+                        name: name.clone(),
+                        chaos_value: *chaos_value,
+                        listed_chaos_value,
+                    });
+                    overridden_gems.push((name, *chaos_value));
+                }
+                None => gems.push((name, listed_chaos_value)),
+            }
+        }
+        outliers.extend(adjust_outliers(
+            skill_gems_response,
+            &mut gems,
+            options.gem_level,
+            options.gem_quality,
+            &options.outliers,
+        ));
+        let projected_gems = options.trend.as_ref().map(|trend| {
+            let (mut projected_gems, declining_gems) = project_pool(
+                skill_gems_response,
+                &gems,
+                color,
+                options.gem_level,
+                options.gem_quality,
+                trend,
+            );
+            declining.extend(declining_gems);
+            projected_gems.extend(overridden_gems.iter().cloned());
+            projected_gems.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+            projected_gems
+        });
+        gems.extend(overridden_gems);
+        gems.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        (gems, projected_gems)
+    };
+    let (red_gems, red_projected) = pool(GemColor::Red);
+    let (green_gems, green_projected) = pool(GemColor::Green);
+    let (blue_gems, blue_projected) = pool(GemColor::Blue);
+    excluded.sort();
+    overridden.sort_by(|a, b| a.name.cmp(&b.name));
+    outliers.sort_by(|a, b| a.name.cmp(&b.name));
+    declining.sort_by(|a, b| a.change_percent.partial_cmp(&b.change_percent).unwrap_or(std::cmp::Ordering::Equal));
+
+    debug!(
+        "Gem counts - Red: {}, Green: {}, Blue: {}",
+        red_gems.len(), green_gems.len(), blue_gems.len()
+    );
+
+    let red_probabilities = offer_probabilities(red_gems.len(), options.offer_size);
+    let green_probabilities = offer_probabilities(green_gems.len(), options.offer_size);
+    let blue_probabilities = offer_probabilities(blue_gems.len(), options.offer_size);
+
+    // Pool sizes don't change with the projection, so neither do the probabilities
+    let projected = options.trend.as_ref().map(|trend| ProjectedValues {
+        hours: trend.hours,
+        red_roi: projected_expected_value(&red_projected, &red_probabilities, options.ignore_after_chaos),
+        green_roi: projected_expected_value(&green_projected, &green_probabilities, options.ignore_after_chaos),
+        blue_roi: projected_expected_value(&blue_projected, &blue_probabilities, options.ignore_after_chaos),
+        declining,
+    });
+
+    CalculationResponse {
+        red_roi: expected_value(&red_gems, &red_probabilities, options.ignore_after_chaos),
+        green_roi: expected_value(&green_gems, &green_probabilities, options.ignore_after_chaos),
+        blue_roi: expected_value(&blue_gems, &blue_probabilities, options.ignore_after_chaos),
+        red_gems: create_gem_values(&red_gems, &red_probabilities, red_projected.as_deref()),
+        green_gems: create_gem_values(&green_gems, &green_probabilities, green_projected.as_deref()),
+        blue_gems: create_gem_values(&blue_gems, &blue_probabilities, blue_projected.as_deref()),
+        converted: None,
+        excluded,
+        overridden,
+        outliers,
+        projected,
+    }
+}
+
+/// Parses a price override written as `NAME:CHAOS`, e.g. `Spark of *:150`.
+pub fn parse_price_override(entry: &str) -> Result<(String, f64), String> {
+    let (name, chaos) = entry
+        .rsplit_once(':')
+        .ok_or_else(|| format!("expected NAME:CHAOS, got {:?}", entry))?;
+    let chaos: f64 = chaos
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid chaos value in {:?}", entry))?;
+    if name.trim().is_empty() || !chaos.is_finite() || chaos < 0.0 {
+        return Err(format!("expected a gem name and a chaos value of zero or more, got {:?}", entry));
+    }
+    Ok((name.trim().to_string(), chaos))
+}
+
+/// Whether a gem name matches a name or pattern, ignoring case. `*` in a pattern
+/// matches any run of characters, so `"Spark of *"` matches every transfigured Spark.
+pub fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let name = name.to_lowercase();
+
+    let mut parts = pattern.split('*');
+    // split always yields at least one part
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No `*`: the whole name has to match
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Chance that each of `n` gems, ranked most valuable first, is the best of three
+/// drawn without replacement. Empty when fewer than three gems exist.
+pub fn calculate_probability(n: usize) -> Vec<f64> {
+    offer_probabilities(n, DEFAULT_OFFER_SIZE)
+}
+
+/// Chance that each of `n` gems, ranked most valuable first, is the best of
+/// `offer_size` drawn without replacement. Empty when fewer than `offer_size` gems
+/// exist; gems that can never be the best are left out.
+pub fn offer_probabilities(n: usize, offer_size: usize) -> Vec<f64> {
+    if offer_size == 0 || n < offer_size {
+        return vec![];
+    }
+
+    // The gem at rank i is the best when the other offer_size - 1 gems all come
+    // from the n - 1 - i gems ranked below it
+    let total_outcomes = binomial(n, offer_size);
+    (0..=n - offer_size)
+        .map(|i| binomial(n - 1 - i, offer_size - 1) / total_outcomes)
+        .collect()
+}
+
+fn binomial(n: usize, k: usize) -> f64 {
+    (0..k).fold(1.0, |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+}
+
+/// Expected chaos value of one craft over gems sorted most valuable first. Gems
+/// worth less than `ignore_threshold` count as 0.
+pub fn calculate_roi_for_gems(gems: &[(String, f64)], ignore_threshold: f64) -> f64 {
+    expected_value(gems, &calculate_probability(gems.len()), ignore_threshold)
+}
+
+/// Sum of each gem's value weighted by its probability of being kept. Gems worth
+/// less than `ignore_threshold` count as 0.
+pub fn expected_value(gems: &[(String, f64)], probabilities: &[f64], ignore_threshold: f64) -> f64 {
+    gems.iter()
+        .zip(probabilities)
+        // Folded from 0.0 because an empty f64 sum is -0.0
+        .fold(0.0, |total, ((_, value), probability)| {
+            let gem_value = if *value >= ignore_threshold { *value } else { 0.0 };
+            total + probability * gem_value
+        })
+}
+
+fn projected_expected_value(projected: &Option<Vec<(String, f64)>>, probabilities: &[f64], ignore_threshold: f64) -> f64 {
+    projected
+        .as_deref()
+        .map_or(0.0, |gems| expected_value(gems, probabilities, ignore_threshold))
+}
+
+fn create_gem_values(gems: &[(String, f64)], probabilities: &[f64], projected: Option<&[(String, f64)]>) -> Vec<GemValue> {
+    gems.iter()
+        .zip(probabilities.iter())
+        .map(|((name, chaos_value), probability)| GemValue {
+            name: name.clone(),
+            chaos_value: *chaos_value,
+            probability: *probability,
+            trade_url: None,
+            ninja_url: None,
+            projected_chaos_value: projected
+                .and_then(|gems| gems.iter().find(|(projected_name, _)| projected_name == name))
+                .map(|(_, value)| *value),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calculate_probability() {
+        assert!(calculate_probability(2).is_empty());
+        assert_eq!(calculate_probability(3), vec![1.0]);
+
+        let probabilities = calculate_probability(5);
+        assert_eq!(probabilities.len(), 3);
+        assert!((probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!((probabilities[0] - 0.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_calculate_roi_for_gems() {
+        let gems = vec![
+            ("A".to_string(), 100.0),
+            ("B".to_string(), 10.0),
+            ("C".to_string(), 1.0),
+            ("D".to_string(), 1.0),
+        ];
+
+        // Best of three from four: the top gem shows up 3/4 of the time, the second 1/4
+        assert!((calculate_roi_for_gems(&gems, 0.0) - 77.5).abs() < 1e-9);
+        assert!((calculate_roi_for_gems(&gems, 50.0) - 75.0).abs() < 1e-9);
+        assert_eq!(calculate_roi_for_gems(&gems[..2], 0.0), 0.0);
+        assert!(calculate_roi_for_gems(&[], 0.0).is_sign_positive());
+    }
+
+    #[test]
+    fn test_offer_probabilities() {
+        assert_eq!(offer_probabilities(5, 3), calculate_probability(5));
+        assert_eq!(offer_probabilities(4, 1), vec![0.25; 4]);
+        assert!(offer_probabilities(3, 4).is_empty());
+        assert!(offer_probabilities(3, 0).is_empty());
+
+        let probabilities = offer_probabilities(10, 4);
+        assert_eq!(probabilities.len(), 7);
+        assert!((probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_calculate_with_options() {
+        // Red gem icon (gd 5): https://web.poecdn.com/gen/image/{base64 of [30,14,{"gd":5}]}/...
+        let icon = "https://web.poecdn.com/gen/image/WzMwLDE0LHsiZ2QiOjV9XQ/x/y.png";
+        let lines: Vec<serde_json::Value> = [("A of B", 100.0), ("C of D", 10.0), ("E of F", 1.0), ("G of H", 1.0)]
+            .iter()
+            .map(|(name, chaos)| serde_json::json!({ "name": name, "chaosValue": chaos, "icon": icon, "tradeFilter": {} }))
+            .collect();
+        let overview: SkillGemResponse = serde_json::from_value(serde_json::json!({ "lines": lines })).unwrap();
+
+        let default = calculate_expected_values(&overview, 1, 0, 0.0);
+        assert!((default.red_roi - 77.5).abs() < 1e-9);
+        assert_eq!(default.red_gems.len(), 2);
+
+        let options = CalculationOptions { offer_size: 1, excluded: vec!["a of b".to_string()], ..CalculationOptions::default() };
+        let result = calculate_with_options(&overview, &options);
+        assert!((result.red_roi - 4.0).abs() < 1e-9);
+        assert_eq!(result.red_gems.len(), 3);
+        assert_eq!(result.excluded, vec!["A of B".to_string()]);
+
+        // A price-fixed listing overridden down falls behind the gems it was ranked above
+        let options = CalculationOptions {
+            price_overrides: BTreeMap::from([("a of *".to_string(), 5.0), ("e of f".to_string(), 50.0)]),
+            ..CalculationOptions::default()
+        };
+        let result = calculate_with_options(&overview, &options);
+        let ranked: Vec<&str> = result.red_gems.iter().map(|gem| gem.name.as_str()).collect();
+        assert_eq!(ranked, vec!["E of F", "C of D"]);
+        assert_eq!(result.overridden.len(), 2);
+        assert_eq!(result.overridden[0].name, "A of B");
+        assert_eq!(result.overridden[0].listed_chaos_value, 100.0);
+        assert!(result.excluded.is_empty());
+    }
+
+    #[test]
+    fn test_parse_price_override() {
+        assert_eq!(parse_price_override("Spark of *: 150"), Ok(("Spark of *".to_string(), 150.0)));
+        assert!(parse_price_override("Spark of Unpredictability").is_err());
+        assert!(parse_price_override("Spark of Unpredictability:lots").is_err());
+        assert!(parse_price_override(":5").is_err());
+        assert!(parse_price_override("Spark:-1").is_err());
+        assert!(parse_price_override("Foo:inf").is_err());
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("spark of unpredictability", "Spark of Unpredictability"));
+        assert!(!matches_pattern("Spark", "Spark of Unpredictability"));
+        assert!(matches_pattern("Spark of *", "Spark of Unpredictability"));
+        assert!(matches_pattern("* of the Zenith", "Molten Strike of the Zenith"));
+        assert!(matches_pattern("*strike*", "Molten Strike of the Zenith"));
+        assert!(matches_pattern("*", "Anything"));
+        assert!(!matches_pattern("a*a", "a"));
+        assert!(!matches_pattern("Cleave of *", "Spark of Unpredictability"));
+    }
+
+    #[test]
+    fn test_matches_variant() {
+        let gem = |level: Option<u32>, quality: Option<u32>, corrupted: Option<bool>| -> SkillGem {
+            serde_json::from_value(serde_json::json!({
+                "id": 1,
+                "name": "Spark of Unpredictability",
+                "gemLevel": level,
+                "gemQuality": quality,
+                "corrupted": corrupted,
+            }))
+            .unwrap()
+        };
+
+        assert!(matches_variant(&gem(None, None, None), 1, 0));
+        assert!(matches_variant(&gem(Some(20), Some(20), Some(false)), 20, 20));
+        assert!(!matches_variant(&gem(Some(20), Some(20), Some(true)), 20, 20));
+        assert!(matches_variant(&gem(Some(21), Some(20), Some(true)), 21, 20));
+        assert!(!matches_variant(&gem(Some(21), Some(20), None), 21, 20));
+    }
+}
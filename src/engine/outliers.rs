@@ -0,0 +1,321 @@
+//! Detection of price-fixed listings at the top of a color's pool.
+//!
+//! The most valuable gem gets the largest probability in the expected value, so a
+//! single listing fixed at an absurd price can dominate a color. A gem is treated as
+//! an outlier when its price is far above the rest of the pool (above the upper
+//! Tukey fence of the log prices) and its listing looks unreliable: few listings, a
+//! sudden jump in its price history, or a price far above its other variants.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::models::{OutlierAdjustment, SkillGem, SkillGemResponse, Sparkline};
+
+use super::matches_variant;
+
+/// Pools smaller than this have too few prices to tell what is unusual.
+const MIN_POOL_SIZE: usize = 4;
+
+/// What happens to a gem detected as an outlier.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OutlierPolicy {
+    /// Outliers are priced as listed
+    #[default]
+    Off,
+    /// Outliers are priced at the pool's upper fence
+    Cap,
+    /// Outliers are priced like the most valuable gem that isn't an outlier
+    Winsorize,
+    /// Outliers are left out of the pool
+    Exclude,
+}
+
+impl OutlierPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutlierPolicy::Off => "off",
+            OutlierPolicy::Cap => "cap",
+            OutlierPolicy::Winsorize => "winsorize",
+            OutlierPolicy::Exclude => "exclude",
+        }
+    }
+}
+
+impl fmt::Display for OutlierPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for OutlierPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        [OutlierPolicy::Off, OutlierPolicy::Cap, OutlierPolicy::Winsorize, OutlierPolicy::Exclude]
+            .into_iter()
+            .find(|policy| policy.as_str().eq_ignore_ascii_case(value))
+            .ok_or_else(|| format!("expected off, cap, winsorize or exclude, got {:?}", value))
+    }
+}
+
+/// How outliers are detected and handled.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutlierOptions {
+    pub policy: OutlierPolicy,
+    /// Gems above the fence with fewer listings than this are outliers
+    pub min_listings: u32,
+    /// Gems above the fence whose price rose more than this many percent over the
+    /// last 7 days are outliers
+    pub max_spike_percent: f64,
+    /// Gems above the fence priced more than this many times their most valuable
+    /// other variant are outliers
+    pub max_variant_ratio: f64,
+    /// Interquartile ranges of log price above the third quartile where the fence sits
+    pub fence_iqr: f64,
+}
+
+impl Default for OutlierOptions {
+    fn default() -> Self {
+        Self {
+            policy: OutlierPolicy::Off,
+            min_listings: 5,
+            max_spike_percent: 300.0,
+            max_variant_ratio: 5.0,
+            fence_iqr: 1.5,
+        }
+    }
+}
+
+/// Detects the outliers in one color's pool and applies the policy to them.
+///
+/// `gems` are the names and prices of the pool in the priced level/quality variant.
+/// Returns what was adjusted; the caller re-sorts the pool afterwards.
+pub fn adjust_outliers(
+    skill_gems_response: &SkillGemResponse,
+    gems: &mut Vec<(String, f64)>,
+    gem_level: u32,
+    gem_quality: u32,
+    options: &OutlierOptions,
+) -> Vec<OutlierAdjustment> {
+    if options.policy == OutlierPolicy::Off || gems.len() < MIN_POOL_SIZE {
+        return Vec::new();
+    }
+    let Some(fence) = upper_fence(gems, options.fence_iqr) else {
+        return Vec::new();
+    };
+
+    let flagged: Vec<(String, Vec<String>)> = gems
+        .iter()
+        .filter(|(_, value)| *value > fence)
+        .filter_map(|(name, value)| {
+            let reasons = suspicion_reasons(skill_gems_response, name, *value, gem_level, gem_quality, options);
+            (!reasons.is_empty()).then(|| (name.clone(), reasons))
+        })
+        .collect();
+    if flagged.is_empty() {
+        return Vec::new();
+    }
+
+    let is_flagged = |name: &str| flagged.iter().any(|(flagged_name, _)| flagged_name == name);
+    let replacement = match options.policy {
+        OutlierPolicy::Cap => Some(fence),
+        OutlierPolicy::Winsorize => Some(
+            gems.iter()
+                .filter(|(name, _)| !is_flagged(name))
+                .map(|(_, value)| *value)
+                .fold(0.0, f64::max),
+        ),
+        OutlierPolicy::Exclude | OutlierPolicy::Off => None,
+    };
+
+    let mut adjustments = Vec::new();
+    gems.retain_mut(|(name, value)| {
+        let Some((_, reasons)) = flagged.iter().find(|(flagged_name, _)| flagged_name == name) else {
+            return true;
+        };
+        adjustments.push(OutlierAdjustment {
+            name: name.clone(),
+            listed_chaos_value: *value,
+            chaos_value: replacement,
+            reasons: reasons.clone(),
+        });
+        match replacement {
+            Some(replacement) => {
+                *value = replacement;
+                true
+            }
+            None => false,
+        }
+    });
+    adjustments
+}
+
+/// Upper Tukey fence of the pool's log prices, in chaos. None when too few gems
+/// have a price.
+fn upper_fence(gems: &[(String, f64)], fence_iqr: f64) -> Option<f64> {
+    let mut logs: Vec<f64> = gems.iter().filter(|(_, value)| *value > 0.0).map(|(_, value)| value.ln()).collect();
+    if logs.len() < MIN_POOL_SIZE {
+        return None;
+    }
+    logs.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+    let q1 = quantile(&logs, 0.25);
+    let q3 = quantile(&logs, 0.75);
+    Some((q3 + fence_iqr * (q3 - q1)).exp())
+}
+
+/// Linearly interpolated quantile of sorted values.
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = q * (sorted.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+/// Why a gem's listing looks unreliable, empty when it looks fine.
+fn suspicion_reasons(
+    skill_gems_response: &SkillGemResponse,
+    name: &str,
+    value: f64,
+    gem_level: u32,
+    gem_quality: u32,
+    options: &OutlierOptions,
+) -> Vec<String> {
+    let variants: Vec<&SkillGem> = skill_gems_response.lines.iter().filter(|gem| gem.name == name).collect();
+    let Some(listing) = variants
+        .iter()
+        .find(|gem| gem.trade_filter.is_some() && matches_variant(gem, gem_level, gem_quality))
+    else {
+        return Vec::new();
+    };
+
+    let mut reasons = Vec::new();
+
+    let listings = listing.listing_count.unwrap_or(0);
+    if listings < options.min_listings {
+        reasons.push(format!("only {} listings", listings));
+    }
+
+    match history_sparkline(listing).map(|sparkline| sparkline.total_change) {
+        None => reasons.push("no reliable price history".to_string()),
+        Some(Some(change)) if change > options.max_spike_percent => {
+            reasons.push(format!("price up {:.0}% in 7 days", change));
+        }
+        _ => {}
+    }
+
+    let other_variant = variants
+        .iter()
+        .filter(|gem| !gem.corrupted.unwrap_or(false) && !matches_variant(gem, gem_level, gem_quality))
+        .filter_map(|gem| Some((gem.chaos_value.filter(|chaos| *chaos > 0.0)?, *gem)))
+        .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    if let Some((other_value, other)) = other_variant {
+        if value > options.max_variant_ratio * other_value {
+            reasons.push(format!(
+                "{:.0}x the price of its {}/{} variant",
+                value / other_value,
+                other.gem_level.unwrap_or(1),
+                other.gem_quality.unwrap_or(0)
+            ));
+        }
+    }
+
+    reasons
+}
+
+/// The sparkline with price history: the regular one, otherwise the low-confidence
+/// one poe.ninja gives thinly traded gems.
+fn history_sparkline(gem: &SkillGem) -> Option<&Sparkline> {
+    [gem.sparkline.as_ref(), gem.low_confidence_sparkline.as_ref()]
+        .into_iter()
+        .flatten()
+        .find(|sparkline| sparkline.data.as_ref().is_some_and(|data| data.iter().any(Option::is_some)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overview() -> SkillGemResponse {
+        let stable = serde_json::json!({ "data": [1.0, 2.0, 0.0], "totalChange": 5.0 });
+        let mut lines: Vec<serde_json::Value> = (10..22)
+            .map(|chaos| {
+                serde_json::json!({ "name": format!("Gem {}", chaos), "chaosValue": chaos, "listingCount": 50, "sparkline": stable, "tradeFilter": {} })
+            })
+            .collect();
+        // Price-fixed: one listing, no history
+        lines.push(serde_json::json!({ "name": "Fixed", "chaosValue": 2000.0, "listingCount": 1, "tradeFilter": {} }));
+        // Expensive but well traded
+        lines.push(serde_json::json!({ "name": "Popular", "chaosValue": 1500.0, "listingCount": 80, "sparkline": stable, "tradeFilter": {} }));
+        lines.push(serde_json::json!({ "name": "Popular", "gemLevel": 20, "gemQuality": 20, "chaosValue": 1800.0, "tradeFilter": {} }));
+        serde_json::from_value(serde_json::json!({ "lines": lines })).unwrap()
+    }
+
+    fn pool(overview: &SkillGemResponse) -> Vec<(String, f64)> {
+        overview
+            .lines
+            .iter()
+            .filter(|gem| gem.gem_level.is_none())
+            .map(|gem| (gem.name.clone(), gem.chaos_value.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_outlier_policies() {
+        let overview = overview();
+        let options = |policy| OutlierOptions { policy, ..OutlierOptions::default() };
+
+        let mut gems = pool(&overview);
+        assert!(adjust_outliers(&overview, &mut gems, 1, 0, &options(OutlierPolicy::Off)).is_empty());
+
+        let adjustments = adjust_outliers(&overview, &mut gems, 1, 0, &options(OutlierPolicy::Exclude));
+        assert_eq!(adjustments.len(), 1);
+        assert_eq!(adjustments[0].name, "Fixed");
+        assert_eq!(adjustments[0].chaos_value, None);
+        assert_eq!(adjustments[0].reasons, vec!["only 1 listings", "no reliable price history"]);
+        assert!(gems.iter().all(|(name, _)| name != "Fixed"));
+        assert!(gems.iter().any(|(name, _)| name == "Popular"));
+
+        let mut gems = pool(&overview);
+        adjust_outliers(&overview, &mut gems, 1, 0, &options(OutlierPolicy::Winsorize));
+        assert_eq!(gems.iter().find(|(name, _)| name == "Fixed").unwrap().1, 1500.0);
+
+        let mut gems = pool(&overview);
+        let adjustments = adjust_outliers(&overview, &mut gems, 1, 0, &options(OutlierPolicy::Cap));
+        let capped = adjustments[0].chaos_value.unwrap();
+        assert!(capped > 21.0 && capped < 1500.0);
+    }
+
+    #[test]
+    fn test_variant_spread_and_spikes() {
+        let overview: SkillGemResponse = serde_json::from_value(serde_json::json!({ "lines": [
+            { "name": "Spiked", "chaosValue": 900.0, "listingCount": 40, "sparkline": { "data": [1.0, 900.0], "totalChange": 900.0 }, "tradeFilter": {} },
+            { "name": "Spiked", "gemLevel": 20, "gemQuality": 20, "chaosValue": 100.0 },
+        ] }))
+        .unwrap();
+
+        let reasons = suspicion_reasons(&overview, "Spiked", 900.0, 1, 0, &OutlierOptions::default());
+        assert_eq!(reasons, vec!["price up 900% in 7 days", "9x the price of its 20/20 variant"]);
+
+        // Thinly traded gems only have the low-confidence history
+        let overview: SkillGemResponse = serde_json::from_value(serde_json::json!({ "lines": [
+            { "name": "Thin", "chaosValue": 900.0, "listingCount": 40, "sparkline": { "data": [], "totalChange": 0.0 }, "lowConfidenceSparkline": { "data": [1.0, 900.0], "totalChange": 900.0 }, "tradeFilter": {} },
+            { "name": "Steady", "chaosValue": 50.0, "listingCount": 40, "lowConfidenceSparkline": { "data": [1.0, 2.0], "totalChange": 2.0 }, "tradeFilter": {} },
+        ] }))
+        .unwrap();
+        let reasons = suspicion_reasons(&overview, "Thin", 900.0, 1, 0, &OutlierOptions::default());
+        assert_eq!(reasons, vec!["price up 900% in 7 days"]);
+        assert!(suspicion_reasons(&overview, "Steady", 50.0, 1, 0, &OutlierOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn test_outlier_policy_from_str() {
+        assert_eq!("Winsorize".parse(), Ok(OutlierPolicy::Winsorize));
+        assert!("trim".parse::<OutlierPolicy>().is_err());
+    }
+}
@@ -0,0 +1,230 @@
+//! Projection of gem prices from poe.ninja's 7-day sparklines.
+//!
+//! A sparkline holds one point per day for the last 7 days, each the price change in
+//! percent since the first day. The model assumes the price keeps changing by the
+//! same percentage every day: a least-squares line is fitted through the logarithm
+//! of the sparkline's price ratios, and its slope, the daily log growth, is carried
+//! forward from the current price:
+//!
+//! ```text
+//! projected = price * exp(slope * hours / 24)
+//! ```
+//!
+//! The projected change is capped at `max_change_percent` either way, and gems with
+//! fewer than 3 days of history keep their current price.
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{DecliningGem, GemColor, SkillGem, SkillGemResponse, Sparkline};
+
+use super::matches_variant;
+
+/// Furthest prices are projected ahead, the 7 days a sparkline covers.
+pub const MAX_PROJECTION_HOURS: f64 = 168.0;
+
+/// Fewer days of history than this give no trend.
+const MIN_POINTS: usize = 3;
+
+/// How prices are projected and when a gem counts as declining.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrendOptions {
+    /// Hours ahead prices are projected
+    pub hours: f64,
+    /// Largest projected change in percent, up or down
+    pub max_change_percent: f64,
+    /// Gems whose price fell more than this many percent over the last 7 days are
+    /// flagged as declining
+    pub decline_percent: f64,
+}
+
+impl Default for TrendOptions {
+    fn default() -> Self {
+        Self {
+            hours: 24.0,
+            max_change_percent: 100.0,
+            decline_percent: 25.0,
+        }
+    }
+}
+
+/// Daily growth of the price's logarithm, fitted to a sparkline. None with fewer
+/// than 3 usable days.
+pub fn daily_log_growth(sparkline: &Sparkline) -> Option<f64> {
+    let points: Vec<(f64, f64)> = sparkline
+        .data
+        .as_ref()?
+        .iter()
+        .enumerate()
+        .filter_map(|(day, change)| {
+            let ratio = 1.0 + (*change)? / 100.0;
+            (ratio > 0.0).then(|| (day as f64, ratio.ln()))
+        })
+        .collect();
+    if points.len() < MIN_POINTS {
+        return None;
+    }
+
+    let count = points.len() as f64;
+    let mean_day = points.iter().map(|(day, _)| day).sum::<f64>() / count;
+    let mean_log = points.iter().map(|(_, log)| log).sum::<f64>() / count;
+    let covariance: f64 = points.iter().map(|(day, log)| (day - mean_day) * (log - mean_log)).sum();
+    let variance: f64 = points.iter().map(|(day, _)| (day - mean_day).powi(2)).sum();
+    Some(covariance / variance)
+}
+
+/// The sparkline with enough history to fit a trend: the regular one, otherwise
+/// the low-confidence one poe.ninja gives thinly traded gems.
+fn trend_sparkline(gem: &SkillGem) -> Option<&Sparkline> {
+    [gem.sparkline.as_ref(), gem.low_confidence_sparkline.as_ref()]
+        .into_iter()
+        .flatten()
+        .find(|sparkline| daily_log_growth(sparkline).is_some())
+}
+
+/// Factor a gem's price is projected to change by in `options.hours`; 1 without a trend.
+pub fn projection_factor(gem: &SkillGem, options: &TrendOptions) -> f64 {
+    let Some(growth) = trend_sparkline(gem).and_then(daily_log_growth) else {
+        return 1.0;
+    };
+    let max_factor = 1.0 + options.max_change_percent.max(0.0) / 100.0;
+    (growth * options.hours / 24.0).exp().clamp(1.0 / max_factor, max_factor)
+}
+
+/// The gem's price change over the last 7 days in percent when it fell more than
+/// `options.decline_percent`, from the same sparkline its projection uses.
+pub fn sharp_decline(gem: &SkillGem, options: &TrendOptions) -> Option<f64> {
+    let sparkline = trend_sparkline(gem)?;
+    let change = sparkline.total_change.or_else(|| {
+        sparkline.data.as_ref()?.iter().rev().find_map(|change| *change)
+    })?;
+    (change < -options.decline_percent).then_some(change)
+}
+
+/// Projects the prices of one color's pool and lists its gems in sharp decline.
+///
+/// `gems` are the names and prices of the pool in the priced level/quality variant;
+/// each price moves by its listing's [`projection_factor`]. Returns the projected
+/// pool, most valuable first, and the declining gems.
+pub fn project_pool(
+    skill_gems_response: &SkillGemResponse,
+    gems: &[(String, f64)],
+    color: GemColor,
+    gem_level: u32,
+    gem_quality: u32,
+    options: &TrendOptions,
+) -> (Vec<(String, f64)>, Vec<DecliningGem>) {
+    let mut projected = Vec::with_capacity(gems.len());
+    let mut declining = Vec::new();
+    for (name, value) in gems {
+        let listing = skill_gems_response
+            .lines
+            .iter()
+            .find(|gem| &gem.name == name && gem.trade_filter.is_some() && matches_variant(gem, gem_level, gem_quality));
+        let Some(listing) = listing else {
+            projected.push((name.clone(), *value));
+            continue;
+        };
+
+        let projected_value = value * projection_factor(listing, options);
+        if let Some(change_percent) = sharp_decline(listing, options) {
+            declining.push(DecliningGem {
+                name: name.clone(),
+                color: color.as_str().to_string(),
+                chaos_value: *value,
+                projected_chaos_value: projected_value,
+                change_percent,
+            });
+        }
+        projected.push((name.clone(), projected_value));
+    }
+
+    projected.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    (projected, declining)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gem(data: serde_json::Value) -> SkillGem {
+        serde_json::from_value(serde_json::json!({ "name": "Spark", "sparkline": data })).unwrap()
+    }
+
+    #[test]
+    fn test_projection_factor() {
+        let options = TrendOptions::default();
+
+        // Doubling every day, projected one day ahead
+        let rising = gem(serde_json::json!({ "data": [0.0, 100.0, 300.0, 700.0], "totalChange": 700.0 }));
+        assert!((daily_log_growth(rising.sparkline.as_ref().unwrap()).unwrap() - 2f64.ln()).abs() < 1e-9);
+        assert!((projection_factor(&rising, &TrendOptions { max_change_percent: 500.0, ..options.clone() }) - 2.0).abs() < 1e-9);
+        // Capped at max_change_percent
+        assert_eq!(projection_factor(&rising, &TrendOptions { max_change_percent: 50.0, ..options.clone() }), 1.5);
+
+        let halving = gem(serde_json::json!({ "data": [0.0, -50.0, -75.0], "totalChange": -75.0 }));
+        let factor = projection_factor(&halving, &TrendOptions { hours: 12.0, ..options.clone() });
+        assert!((factor - 0.5f64.sqrt()).abs() < 1e-9);
+        assert_eq!(sharp_decline(&halving, &options), Some(-75.0));
+
+        // Too little history: no trend
+        let short = gem(serde_json::json!({ "data": [null, null, 0.0, 50.0], "totalChange": 50.0 }));
+        assert_eq!(projection_factor(&short, &options), 1.0);
+        assert_eq!(sharp_decline(&short, &options), None);
+
+        // Thinly traded gems come with an empty regular sparkline
+        let thin: SkillGem = serde_json::from_value(serde_json::json!({
+            "name": "Spark",
+            "sparkline": { "data": [], "totalChange": 0.0 },
+            "lowConfidenceSparkline": { "data": [0.0, -50.0, -75.0], "totalChange": -75.0 }
+        }))
+        .unwrap();
+        assert!(projection_factor(&thin, &options) < 1.0);
+        assert_eq!(sharp_decline(&thin, &options), Some(-75.0));
+    }
+
+    #[test]
+    fn test_projected_expected_value() {
+        use crate::engine::{calculate_with_options, CalculationOptions};
+        use std::collections::BTreeMap;
+
+        // Red gem icon (gd 5)
+        let icon = "https://web.poecdn.com/gen/image/WzMwLDE0LHsiZ2QiOjV9XQ/x/y.png";
+        let lines: Vec<serde_json::Value> = [
+            ("A of B", 100.0, serde_json::json!([0.0, -50.0, -75.0]), -75.0),
+            ("C of D", 10.0, serde_json::json!([0.0, 100.0, 300.0]), 300.0),
+            ("E of F", 1.0, serde_json::json!([]), 0.0),
+            ("G of H", 1.0, serde_json::json!([0.0, 100.0, 300.0]), 300.0),
+        ]
+        .iter()
+        .map(|(name, chaos, data, total_change)| {
+            serde_json::json!({
+                "name": name, "chaosValue": chaos, "icon": icon, "tradeFilter": {},
+                "sparkline": { "data": data, "totalChange": total_change }
+            })
+        })
+        .collect();
+        let overview: SkillGemResponse = serde_json::from_value(serde_json::json!({ "lines": lines })).unwrap();
+
+        let options = CalculationOptions {
+            price_overrides: BTreeMap::from([("g of h".to_string(), 30.0)]),
+            trend: Some(TrendOptions::default()),
+            ..CalculationOptions::default()
+        };
+        let result = calculate_with_options(&overview, &options);
+        assert!((result.red_roi - 82.5).abs() < 1e-9);
+
+        // A halves and C doubles in a day; the overridden G keeps its price
+        let projected = result.projected.unwrap();
+        assert!((projected.red_roi - 45.0).abs() < 1e-9);
+        let values: Vec<(&str, Option<f64>)> = result
+            .red_gems
+            .iter()
+            .map(|gem| (gem.name.as_str(), gem.projected_chaos_value))
+            .collect();
+        assert_eq!(values, vec![("A of B", Some(50.0)), ("G of H", Some(30.0))]);
+        assert_eq!(projected.declining.len(), 1);
+        assert_eq!(projected.declining[0].name, "A of B");
+        assert_eq!(projected.declining[0].color, "red");
+    }
+}
@@ -0,0 +1,410 @@
+//! Text exports of calculation results and gem prices: CSV and TSV for spreadsheets,
+//! Markdown tables, and a short summary to paste into Discord.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::models::{CalculationResponse, GemColor, GemValue, SkillGemResponse};
+
+/// Gems listed per color in the Discord summary, most valuable first.
+const DISCORD_GEMS_PER_COLOR: usize = 3;
+
+/// Gems listed in the Discord summary of a price overview.
+const DISCORD_GEMS: usize = 10;
+
+/// Representation of an exported result.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// The JSON API response
+    #[default]
+    Json,
+    /// Comma-separated values with a header row, one row per gem
+    Csv,
+    /// Tab-separated values, which paste straight into spreadsheet cells
+    Tsv,
+    /// Markdown tables
+    Markdown,
+    /// A few lines of Discord-flavoured Markdown with the best gems per color
+    Discord,
+}
+
+impl ExportFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Tsv => "tsv",
+            ExportFormat::Markdown => "markdown",
+            ExportFormat::Discord => "discord",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Tsv => "text/tab-separated-values; charset=utf-8",
+            ExportFormat::Markdown => "text/markdown; charset=utf-8",
+            ExportFormat::Discord => "text/plain; charset=utf-8",
+        }
+    }
+
+    /// The first media type of an `Accept` header that has an export format.
+    /// `text/plain` is the Discord summary; anything unknown is skipped.
+    pub fn from_accept(accept: &str) -> Option<Self> {
+        accept.split(',').find_map(|media_range| {
+            let mut parts = media_range.split(';').map(str::trim);
+            let media_type = parts.next()?.to_ascii_lowercase();
+            // q=0 means "not acceptable"
+            if parts.any(|param| param.replace(' ', "") == "q=0") {
+                return None;
+            }
+            match media_type.as_str() {
+                "application/json" | "*/*" => Some(ExportFormat::Json),
+                "text/csv" => Some(ExportFormat::Csv),
+                "text/tab-separated-values" => Some(ExportFormat::Tsv),
+                "text/markdown" => Some(ExportFormat::Markdown),
+                "text/plain" => Some(ExportFormat::Discord),
+                _ => None,
+            }
+        })
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        [
+            ExportFormat::Json,
+            ExportFormat::Csv,
+            ExportFormat::Tsv,
+            ExportFormat::Markdown,
+            ExportFormat::Discord,
+        ]
+        .into_iter()
+        .find(|format| format.as_str().eq_ignore_ascii_case(value))
+        .ok_or_else(|| format!("expected json, csv, tsv, markdown or discord, got {:?}", value))
+    }
+}
+
+/// The prices an export was calculated from.
+#[derive(Debug, Clone, Copy)]
+pub struct PriceSnapshot<'a> {
+    pub league: &'a str,
+    /// When the prices were fetched from poe.ninja
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Renders a calculation: the expected value per color and every gem's value and
+/// probability of being kept.
+pub fn render_calculation(
+    format: ExportFormat,
+    response: &CalculationResponse,
+    snapshot: PriceSnapshot,
+    gem_level: u32,
+    gem_quality: u32,
+) -> String {
+    let colors = [
+        (GemColor::Red, response.red_roi, &response.red_gems),
+        (GemColor::Green, response.green_roi, &response.green_gems),
+        (GemColor::Blue, response.blue_roi, &response.blue_gems),
+    ];
+    let updated_at = timestamp(snapshot.updated_at);
+
+    match format {
+        ExportFormat::Json => serde_json::to_string(response).unwrap_or_default(),
+        ExportFormat::Csv | ExportFormat::Tsv => {
+            let headers = [
+                "league", "prices_updated_at", "gem_level", "gem_quality", "color", "expected_value",
+                "gem", "chaos_value", "probability",
+            ];
+            let updated_at = &updated_at;
+            let rows = colors
+                .iter()
+                .flat_map(|(color, roi, gems)| {
+                    gems.iter().map(move |gem| {
+                        vec![
+                            snapshot.league.to_string(),
+                            updated_at.clone(),
+                            gem_level.to_string(),
+                            gem_quality.to_string(),
+                            color.as_str().to_string(),
+                            number(*roi),
+                            gem.name.clone(),
+                            number(gem.chaos_value),
+                            gem.probability.to_string(),
+                        ]
+                    })
+                })
+                .collect::<Vec<_>>();
+            delimited(format, &headers, &rows)
+        }
+        ExportFormat::Markdown => {
+            let mut text = format!(
+                "## Transfigure expected value: {} {}/{}\n\nPrices from poe.ninja at {}\n\n",
+                snapshot.league, gem_level, gem_quality, updated_at
+            );
+            let summary = colors
+                .iter()
+                .map(|(color, roi, gems)| vec![title(*color).to_string(), chaos(*roi), gems.len().to_string()])
+                .collect::<Vec<_>>();
+            text.push_str(&markdown_table(&["Color", "Expected value", "Gems"], &[false, true, true], &summary));
+
+            for (color, _, gems) in &colors {
+                let rows = gems
+                    .iter()
+                    .map(|gem| vec![gem.name.clone(), chaos(gem.chaos_value), percent(gem.probability)])
+                    .collect::<Vec<_>>();
+                text.push_str(&format!("\n### {}\n\n", title(*color)));
+                text.push_str(&markdown_table(&["Gem", "Value", "Probability"], &[false, true, true], &rows));
+            }
+            text
+        }
+        ExportFormat::Discord => {
+            let best = colors
+                .iter()
+                .map(|(_, roi, _)| *roi)
+                .fold(f64::NEG_INFINITY, f64::max);
+            let mut text = format!(
+                "**Transfigure EV · {} · {}/{}** (poe.ninja, {})\n",
+                snapshot.league, gem_level, gem_quality, updated_at
+            );
+            for (color, roi, gems) in &colors {
+                let ev = if *roi == best && best > 0.0 {
+                    format!("**{}**", chaos(*roi))
+                } else {
+                    chaos(*roi)
+                };
+                let top = top_gems(gems)
+                    .map(|gem| format!("{} {} ({})", gem.name, chaos(gem.chaos_value), percent(gem.probability)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                text.push_str(&format!("> {}: {}", title(*color), ev));
+                if !top.is_empty() {
+                    text.push_str(&format!(" — {}", top));
+                }
+                text.push('\n');
+            }
+            text
+        }
+    }
+}
+
+/// Renders a poe.ninja skill gem overview, one row per gem variant, most valuable first.
+pub fn render_skill_gems(format: ExportFormat, response: &SkillGemResponse, snapshot: PriceSnapshot) -> String {
+    let mut gems: Vec<_> = response.lines.iter().collect();
+    gems.sort_by(|a, b| {
+        b.chaos_value
+            .unwrap_or(0.0)
+            .partial_cmp(&a.chaos_value.unwrap_or(0.0))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let rows = gems
+        .iter()
+        .map(|gem| {
+            vec![
+                gem.name.clone(),
+                gem.icon
+                    .as_deref()
+                    .and_then(GemColor::from_icon_url)
+                    .map_or(String::new(), |color| color.as_str().to_string()),
+                gem.gem_level.unwrap_or(1).to_string(),
+                gem.gem_quality.unwrap_or(0).to_string(),
+                gem.corrupted.unwrap_or(false).to_string(),
+                gem.chaos_value.map_or(String::new(), number),
+                gem.divine_value.map_or(String::new(), number),
+                gem.listing_count.map_or(String::new(), |count| count.to_string()),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let updated_at = timestamp(snapshot.updated_at);
+
+    match format {
+        ExportFormat::Json => serde_json::to_string(response).unwrap_or_default(),
+        ExportFormat::Csv | ExportFormat::Tsv => {
+            let headers = [
+                "league", "prices_updated_at", "gem", "color", "gem_level", "gem_quality", "corrupted",
+                "chaos_value", "divine_value", "listing_count",
+            ];
+            let rows = rows
+                .into_iter()
+                .map(|row| [vec![snapshot.league.to_string(), updated_at.clone()], row].concat())
+                .collect::<Vec<_>>();
+            delimited(format, &headers, &rows)
+        }
+        ExportFormat::Markdown => format!(
+            "## Skill gem prices: {}\n\nPrices from poe.ninja at {}\n\n{}",
+            snapshot.league,
+            updated_at,
+            markdown_table(
+                &["Gem", "Color", "Level", "Quality", "Corrupted", "Chaos", "Divine", "Listings"],
+                &[false, false, true, true, false, true, true, true],
+                &rows,
+            )
+        ),
+        ExportFormat::Discord => {
+            let mut text = format!(
+                "**Most valuable skill gems · {}** (poe.ninja, {})\n",
+                snapshot.league, updated_at
+            );
+            for gem in gems.iter().take(DISCORD_GEMS) {
+                text.push_str(&format!(
+                    "> {} {}/{}{}: {}\n",
+                    gem.name,
+                    gem.gem_level.unwrap_or(1),
+                    gem.gem_quality.unwrap_or(0),
+                    if gem.corrupted.unwrap_or(false) { " corrupted" } else { "" },
+                    chaos(gem.chaos_value.unwrap_or(0.0))
+                ));
+            }
+            text
+        }
+    }
+}
+
+/// The most valuable gems of a color for the Discord summary.
+fn top_gems(gems: &[GemValue]) -> impl Iterator<Item = &GemValue> {
+    let mut sorted: Vec<&GemValue> = gems.iter().collect();
+    sorted.sort_by(|a, b| b.chaos_value.partial_cmp(&a.chaos_value).unwrap_or(std::cmp::Ordering::Equal));
+    sorted.into_iter().take(DISCORD_GEMS_PER_COLOR)
+}
+
+/// CSV (RFC 4180 quoting) or TSV (tabs and line breaks in cells become spaces).
+fn delimited(format: ExportFormat, headers: &[&str], rows: &[Vec<String>]) -> String {
+    let (separator, cell): (&str, fn(&str) -> String) = match format {
+        ExportFormat::Tsv => ("\t", |value| value.replace(['\t', '\r', '\n'], " ")),
+        _ => (",", |value| {
+            if value.contains([',', '"', '\r', '\n']) {
+                format!("\"{}\"", value.replace('"', "\"\""))
+            } else {
+                value.to_string()
+            }
+        }),
+    };
+
+    let mut text = headers.join(separator);
+    text.push_str("\r\n");
+    for row in rows {
+        text.push_str(&row.iter().map(|value| cell(value)).collect::<Vec<_>>().join(separator));
+        text.push_str("\r\n");
+    }
+    text
+}
+
+/// A Markdown table; `right` marks the columns aligned to the right.
+fn markdown_table(headers: &[&str], right: &[bool], rows: &[Vec<String>]) -> String {
+    let escape = |value: &str| value.replace('|', "\\|");
+    let mut text = format!("| {} |\n", headers.join(" | "));
+    text.push_str(&format!(
+        "|{}|\n",
+        right
+            .iter()
+            .map(|right| if *right { " ---: " } else { " --- " })
+            .collect::<Vec<_>>()
+            .join("|")
+    ));
+    for row in rows {
+        text.push_str(&format!("| {} |\n", row.iter().map(|value| escape(value)).collect::<Vec<_>>().join(" | ")));
+    }
+    text
+}
+
+fn title(color: GemColor) -> &'static str {
+    match color {
+        GemColor::Red => "Red",
+        GemColor::Green => "Green",
+        GemColor::Blue => "Blue",
+    }
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+/// A value for spreadsheets: plain, with no unit or thousands separator.
+fn number(value: f64) -> String {
+    format!("{:.2}", value)
+}
+
+fn chaos(value: f64) -> String {
+    format!("{:.2}c", value)
+}
+
+fn percent(probability: f64) -> String {
+    format!("{:.2}%", probability * 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calculation() -> CalculationResponse {
+        serde_json::from_value(serde_json::json!({
+            "red_roi": 12.5, "green_roi": 0.0, "blue_roi": 40.0,
+            "red_gems": [{ "name": "Cleave of Rage", "chaos_value": 25.0, "probability": 0.5 }],
+            "green_gems": [],
+            "blue_gems": [
+                { "name": "Spark of Unpredictability, \"the\" best", "chaos_value": 100.0, "probability": 0.4 },
+                { "name": "Arc of Surging", "chaos_value": 0.0, "probability": 0.6 }
+            ],
+        }))
+        .unwrap()
+    }
+
+    fn snapshot() -> PriceSnapshot<'static> {
+        PriceSnapshot {
+            league: "Standard",
+            updated_at: DateTime::parse_from_rfc3339("2026-10-18T12:30:00Z").unwrap().with_timezone(&Utc),
+        }
+    }
+
+    #[test]
+    fn test_render_calculation_delimited() {
+        let csv = render_calculation(ExportFormat::Csv, &calculation(), snapshot(), 20, 20);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("league,prices_updated_at,gem_level"));
+        assert_eq!(lines[1], "Standard,2026-10-18 12:30 UTC,20,20,red,12.50,Cleave of Rage,25.00,0.5");
+        assert!(lines[2].contains(",\"Spark of Unpredictability, \"\"the\"\" best\",100.00,0.4"));
+
+        let tsv = render_calculation(ExportFormat::Tsv, &calculation(), snapshot(), 20, 20);
+        assert_eq!(tsv.lines().nth(1).unwrap().split('\t').count(), 9);
+    }
+
+    #[test]
+    fn test_render_calculation_text() {
+        let markdown = render_calculation(ExportFormat::Markdown, &calculation(), snapshot(), 1, 0);
+        assert!(markdown.contains("Prices from poe.ninja at 2026-10-18 12:30 UTC"));
+        assert!(markdown.contains("| Blue | 40.00c | 2 |"));
+        assert!(markdown.contains("| Cleave of Rage | 25.00c | 50.00% |"));
+
+        let discord = render_calculation(ExportFormat::Discord, &calculation(), snapshot(), 1, 0);
+        assert!(discord.contains("> Blue: **40.00c** — Spark of Unpredictability, \"the\" best 100.00c (40.00%)"));
+        assert!(discord.contains("> Green: 0.00c\n"));
+        assert!(discord.len() < 2000);
+    }
+
+    #[test]
+    fn test_export_format_from_accept() {
+        assert_eq!(ExportFormat::from_accept("text/csv"), Some(ExportFormat::Csv));
+        assert_eq!(
+            ExportFormat::from_accept("image/png, text/markdown;q=0.9, */*;q=0.1"),
+            Some(ExportFormat::Markdown)
+        );
+        assert_eq!(ExportFormat::from_accept("text/csv;q=0, application/json"), Some(ExportFormat::Json));
+        assert_eq!(ExportFormat::from_accept("image/png"), None);
+        assert_eq!("TSV".parse(), Ok(ExportFormat::Tsv));
+    }
+}
@@ -0,0 +1,127 @@
+use crate::models::OfficialLeague;
+
+/// League rules that mark a league as having no shared trade economy.
+pub const DEFAULT_EXCLUDED_RULES: [&str; 2] = ["NoParties", "HardMode"];
+
+/// Decides which official leagues are offered, based on their structured rules and
+/// operator-configured allow/deny lists.
+#[derive(Debug, Clone)]
+pub struct LeagueFilter {
+    /// League ids that are always offered, regardless of rules
+    pub allow: Vec<String>,
+    /// League ids that are never offered
+    pub deny: Vec<String>,
+    /// Rule ids (e.g. NoParties for SSF, HardMode for Ruthless) that exclude a league
+    pub excluded_rules: Vec<String>,
+    /// Whether event leagues are offered
+    pub include_events: bool,
+}
+
+impl Default for LeagueFilter {
+    fn default() -> Self {
+        Self {
+            allow: Vec::new(),
+            deny: Vec::new(),
+            excluded_rules: DEFAULT_EXCLUDED_RULES.iter().map(|rule| rule.to_string()).collect(),
+            include_events: false,
+        }
+    }
+}
+
+impl LeagueFilter {
+    /// Whether a league id is on the allow list.
+    pub fn is_allowlisted(&self, id: &str) -> bool {
+        self.allow.iter().any(|allowed| allowed.eq_ignore_ascii_case(id))
+    }
+
+    /// Determines if a league is relevant for economy tracking on POE Ninja.
+    ///
+    /// The deny list wins over everything, the allow list over the rules. Otherwise
+    /// private leagues, events (unless enabled) and leagues with an excluded rule
+    /// such as NoParties (SSF) or HardMode (Ruthless) are dropped.
+    pub fn is_economy_league(&self, league: &OfficialLeague) -> bool {
+        let id = &league.id;
+
+        if self.deny.iter().any(|denied| denied.eq_ignore_ascii_case(id)) {
+            return false;
+        }
+        if self.is_allowlisted(id) {
+            return true;
+        }
+
+        if is_private_league_id(id) {
+            return false;
+        }
+
+        if league.event.unwrap_or(false) && !self.include_events {
+            return false;
+        }
+
+        let has_excluded_rule = league
+            .rules
+            .iter()
+            .flatten()
+            .any(|rule| self.excluded_rules.iter().any(|excluded| excluded == &rule.id));
+
+        !has_excluded_rule
+    }
+}
+
+/// Private leagues are named like "My League (PL12345)".
+pub fn is_private_league_id(id: &str) -> bool {
+    id.trim_end()
+        .strip_suffix(')')
+        .and_then(|rest| rest.rsplit_once("(PL"))
+        .is_some_and(|(_, number)| !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn official(value: serde_json::Value) -> OfficialLeague {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_league_filter_uses_rules() {
+        let filter = LeagueFilter::default();
+
+        assert!(filter.is_economy_league(&official(serde_json::json!({ "id": "Settlers" }))));
+        assert!(filter.is_economy_league(&official(serde_json::json!({
+            "id": "Hardcore Settlers",
+            "rules": [{ "id": "Hardcore" }]
+        }))));
+        assert!(!filter.is_economy_league(&official(serde_json::json!({
+            "id": "SSF Settlers",
+            "rules": [{ "id": "NoParties" }]
+        }))));
+        assert!(!filter.is_economy_league(&official(serde_json::json!({
+            "id": "Ruthless Settlers",
+            "rules": [{ "id": "HardMode" }]
+        }))));
+        assert!(!filter.is_economy_league(&official(serde_json::json!({ "id": "Solo Event", "event": true }))));
+        assert!(!filter.is_economy_league(&official(serde_json::json!({ "id": "Guild League (PL12345)" }))));
+
+        let configured = LeagueFilter {
+            allow: vec!["Ruthless Settlers".to_string()],
+            deny: vec!["Settlers".to_string()],
+            include_events: true,
+            ..LeagueFilter::default()
+        };
+        assert!(!configured.is_economy_league(&official(serde_json::json!({ "id": "Settlers" }))));
+        assert!(configured.is_economy_league(&official(serde_json::json!({
+            "id": "Ruthless Settlers",
+            "rules": [{ "id": "HardMode" }]
+        }))));
+        assert!(configured.is_economy_league(&official(serde_json::json!({ "id": "Solo Event", "event": true }))));
+    }
+
+    #[test]
+    fn test_is_private_league_id() {
+        assert!(is_private_league_id("Foo (PL12345)"));
+        assert!(!is_private_league_id("Settlers"));
+        assert!(!is_private_league_id("Foo (PL)"));
+        assert!(!is_private_league_id("Foo (Phrecia)"));
+    }
+}
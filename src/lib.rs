@@ -0,0 +1,57 @@
+//! Expected value calculator for transfigured gems from the Dedication to the Goddess
+//! lab font in Path of Exile, priced with poe.ninja data.
+//!
+//! The pricing logic has no server dependencies and can be used on its own:
+//!
+//! - [`models`]: poe.ninja and official API types, gem color detection from icon URLs
+//!   ([`models::GemColor::from_icon_url`]) and [`models::is_transfigured_gem`]
+//! - [`engine`]: level/quality variant matching and the expected value per color
+//!   ([`engine::calculate_expected_values`])
+//! - [`filter`]: which official leagues have a trade economy worth pricing
+//!   ([`filter::LeagueFilter`])
+//! - [`links`]: trade site searches and poe.ninja pages for a gem variant
+//!   ([`links::trade_search_url`])
+//! - [`export`]: CSV, TSV, Markdown and Discord renderings of results
+//!   ([`export::render_calculation`])
+//!
+//! ```
+//! use poe_gem_calculator::{engine::calculate_expected_values, models::SkillGemResponse};
+//!
+//! let overview: SkillGemResponse = serde_json::from_str(r#"{ "lines": [] }"#).unwrap();
+//! let result = calculate_expected_values(&overview, 20, 20, 5.0);
+//! assert_eq!(result.red_roi, 0.0);
+//! ```
+//!
+//! With the default `server` feature the crate also contains the web server and the
+//! upstream fetching and caching it is built on: see [`AppState`] and [`create_router`].
+
+pub mod engine;
+pub mod export;
+pub mod filter;
+pub mod links;
+pub mod models;
+
+#[cfg(feature = "server")]
+pub mod alerts;
+#[cfg(feature = "server")]
+pub mod api;
+#[cfg(feature = "server")]
+pub mod assets;
+#[cfg(feature = "server")]
+pub mod cache;
+#[cfg(feature = "server")]
+pub mod config;
+#[cfg(feature = "server")]
+pub mod metrics;
+#[cfg(feature = "server")]
+pub mod ratelimit;
+#[cfg(feature = "server")]
+pub mod shutdown;
+#[cfg(feature = "server")]
+pub mod upstream;
+
+#[cfg(feature = "server")]
+mod server;
+
+#[cfg(feature = "server")]
+pub use server::{create_router, AppState};
@@ -0,0 +1,152 @@
+//! Links from calculation results to the pathofexile.com trade site and poe.ninja.
+
+use serde_json::json;
+
+use crate::{
+    engine::matches_variant,
+    models::{CalculationResponse, SkillGem, SkillGemResponse},
+};
+
+/// Trade site search for exactly this level/quality/corruption variant of a gem in a
+/// league, cheapest online listings first.
+///
+/// Starts from poe.ninja's `tradeFilter`, which identifies transfigured gems the way
+/// the trade site expects, and adds the variant's level, quality and corruption.
+pub fn trade_search_url(trade_url: &str, league: &str, gem: &SkillGem) -> Option<String> {
+    let trade_filter = gem.trade_filter.as_ref()?;
+    let mut query = match trade_filter.get("query") {
+        Some(query) => query.clone(),
+        None if trade_filter.get("type").is_some() => trade_filter.clone(),
+        None => json!({ "type": gem.name }),
+    };
+    if !query.is_object() {
+        return None;
+    }
+
+    let level = gem.gem_level.unwrap_or(1);
+    let quality = gem.gem_quality.unwrap_or(0);
+    let corrupted = gem.corrupted.unwrap_or(false);
+    query["status"] = json!({ "option": "online" });
+    query["filters"]["misc_filters"]["filters"] = json!({
+        "gem_level": { "min": level, "max": level },
+        "quality": { "min": quality, "max": quality },
+        "corrupted": { "option": corrupted.to_string() },
+    });
+
+    let search = json!({ "query": query, "sort": { "price": "asc" } });
+    Some(format!(
+        "{}/{}?q={}",
+        trade_url.trim_end_matches('/'),
+        urlencoding::encode(league),
+        urlencoding::encode(&search.to_string())
+    ))
+}
+
+/// poe.ninja page of a gem variant, with its price history. poe.ninja's details ids
+/// already name the level, quality and corruption.
+pub fn ninja_details_url(ninja_url: &str, league: &str, gem: &SkillGem) -> Option<String> {
+    let details_id = gem.details_id.as_deref()?;
+    Some(format!(
+        "{}/economy/{}/skill-gems/{}",
+        ninja_url.trim_end_matches('/'),
+        ninja_league_slug(league),
+        details_id
+    ))
+}
+
+/// League path segment of poe.ninja's economy pages: `standard`, `hardcore`, or the
+/// challenge league's name in lowercase with `hc` appended for its hardcore version.
+pub fn ninja_league_slug(league: &str) -> String {
+    let slug = |name: &str| -> String {
+        name.chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .map(|c| c.to_ascii_lowercase())
+            .collect()
+    };
+
+    match league.strip_prefix("Hardcore ") {
+        Some(challenge) => format!("{}hc", slug(challenge)),
+        None => slug(league),
+    }
+}
+
+/// Fills in the trade and poe.ninja links of every gem in a calculation.
+pub fn add_links(
+    response: &mut CalculationResponse,
+    skill_gems_response: &SkillGemResponse,
+    league: &str,
+    gem_level: u32,
+    gem_quality: u32,
+    trade_url: &str,
+    ninja_url: &str,
+) {
+    for gem_value in response
+        .red_gems
+        .iter_mut()
+        .chain(response.green_gems.iter_mut())
+        .chain(response.blue_gems.iter_mut())
+    {
+        let Some(gem) = skill_gems_response.lines.iter().find(|gem| {
+            gem.name == gem_value.name && gem.trade_filter.is_some() && matches_variant(gem, gem_level, gem_quality)
+        }) else {
+            continue;
+        };
+        gem_value.trade_url = trade_search_url(trade_url, league, gem);
+        gem_value.ninja_url = ninja_details_url(ninja_url, league, gem);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn decode_query(url: &str) -> Value {
+        let encoded = url.split_once("?q=").unwrap().1;
+        serde_json::from_str(&urlencoding::decode(encoded).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_trade_search_url() {
+        let gem: SkillGem = serde_json::from_value(json!({
+            "name": "Spark of Unpredictability",
+            "gemLevel": 21,
+            "gemQuality": 20,
+            "corrupted": true,
+            "tradeFilter": { "query": { "type": { "option": "Spark", "discriminator": "alt_x" } } },
+        }))
+        .unwrap();
+
+        let url = trade_search_url("https://www.pathofexile.com/trade/search/", "Hardcore Settlers", &gem).unwrap();
+        assert!(url.starts_with("https://www.pathofexile.com/trade/search/Hardcore%20Settlers?q="));
+
+        let search = decode_query(&url);
+        assert_eq!(search["query"]["type"]["discriminator"], "alt_x");
+        let filters = &search["query"]["filters"]["misc_filters"]["filters"];
+        assert_eq!(filters["gem_level"], json!({ "min": 21, "max": 21 }));
+        assert_eq!(filters["quality"], json!({ "min": 20, "max": 20 }));
+        assert_eq!(filters["corrupted"]["option"], "true");
+
+        // Without a query in the trade filter the gem is searched by name
+        let gem: SkillGem = serde_json::from_value(json!({ "name": "Spark", "tradeFilter": {} })).unwrap();
+        let search = decode_query(&trade_search_url("https://trade", "Standard", &gem).unwrap());
+        assert_eq!(search["query"]["type"], "Spark");
+        assert_eq!(search["query"]["filters"]["misc_filters"]["filters"]["gem_level"]["min"], 1);
+
+        let untradeable: SkillGem = serde_json::from_value(json!({ "name": "Spark" })).unwrap();
+        assert!(trade_search_url("https://trade", "Standard", &untradeable).is_none());
+    }
+
+    #[test]
+    fn test_ninja_details_url() {
+        let gem: SkillGem =
+            serde_json::from_value(json!({ "name": "Spark", "detailsId": "spark-20-20c" })).unwrap();
+        assert_eq!(
+            ninja_details_url("https://poe.ninja/", "Hardcore Settlers", &gem).as_deref(),
+            Some("https://poe.ninja/economy/settlershc/skill-gems/spark-20-20c")
+        );
+        assert_eq!(ninja_league_slug("Standard"), "standard");
+        assert_eq!(ninja_league_slug("Hardcore"), "hardcore");
+        assert_eq!(ninja_league_slug("Settlers"), "settlers");
+    }
+}
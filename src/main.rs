@@ -1,72 +1,164 @@
 use anyhow::Result;
-use axum::{
-    http::StatusCode,
-    response::Html,
-    routing::{get, Router},
-};
-use clap::Parser;
-use reqwest::Client;
-use std::{net::SocketAddr, sync::Arc};
-use tower::ServiceBuilder;
-use tower_http::{
-    cors::CorsLayer,
-    services::ServeDir,
-    trace::TraceLayer,
-};
+use clap::{Parser, Subcommand};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+use tokio::{task::JoinSet, time::MissedTickBehavior};
 use tracing::{info, Level};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-mod api;
-mod cache;
-mod models;
+use poe_gem_calculator::{alerts, api, config, create_router, shutdown, AppState};
 
-use cache::FileCache;
+mod cli;
 
+/// How often expired cache entries and saved calculations are deleted while the server runs.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Command-line flags. Options left unset fall back to the config file, environment
+/// variables and built-in defaults (see `config show`).
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Port to run the server on
-    #[arg(short, long, default_value = "3000")]
-    port: u16,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Server options, used when no subcommand is given
+    #[command(flatten)]
+    serve: ServeArgs,
+
+    /// Config file path [default: $POE_GEM_CONFIG or ./poe-gem-calculator.toml if present]
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Cache directory path [default: cache]
+    #[arg(long, global = true)]
+    cache_dir: Option<String>,
+
+    /// Log level [default: info]
+    #[arg(long, global = true)]
+    log_level: Option<String>,
 
-    /// Host to bind the server to
-    #[arg(long, default_value = "0.0.0.0")]
-    host: String,
+    /// League ids to always offer, even if their rules would exclude them
+    #[arg(long, value_delimiter = ',', global = true)]
+    league_allow: Vec<String>,
 
-    /// Cache directory path
-    #[arg(long, default_value = "cache")]
-    cache_dir: String,
+    /// League ids to never offer
+    #[arg(long, value_delimiter = ',', global = true)]
+    league_deny: Vec<String>,
 
-    /// Log level
-    #[arg(long, default_value = "info")]
-    log_level: String,
+    /// League rule ids that exclude a league from the list [default: NoParties,HardMode]
+    #[arg(long, value_delimiter = ',', global = true)]
+    league_exclude_rules: Vec<String>,
+
+    /// Offer event leagues
+    #[arg(long, global = true)]
+    include_event_leagues: bool,
+
+    /// Private league ids such as "My League (PL12345)" to offer when poe.ninja has data for them
+    #[arg(long = "private-league", global = true)]
+    private_leagues: Vec<String>,
 }
 
-#[derive(Clone)]
-pub struct AppState {
-    pub client: Client,
-    pub cache: Arc<FileCache>,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run the web server (default)
+    Serve(ServeArgs),
+    /// Print the expected value per gem color
+    Calculate(cli::CalculateArgs),
+    /// List and filter skill gems
+    Gems(cli::GemsArgs),
+    /// List available leagues
+    Leagues(cli::LeaguesArgs),
+    /// Fetch league and gem data into the cache
+    Fetch(cli::FetchArgs),
+    /// Inspect the effective configuration
+    Config {
+        #[command(subcommand)]
+        action: cli::ConfigCommand,
+    },
 }
 
-impl AppState {
-    pub fn new(cache_dir: &str) -> Result<Self> {
-        let client = Client::builder()
-            .user_agent("poe-gem-calculator/0.1.0")
-            .timeout(std::time::Duration::from_secs(30))
-            .build()?;
+#[derive(clap::Args, Debug)]
+struct ServeArgs {
+    /// Port to run the server on [default: 3000]
+    #[arg(short, long)]
+    port: Option<u16>,
+
+    /// Host to bind the server to [default: 0.0.0.0]
+    #[arg(long)]
+    host: Option<String>,
+
+    /// Serve the frontend from this directory instead of the embedded copy, e.g. `public` while working on it
+    #[arg(long)]
+    static_dir: Option<String>,
+}
 
-        let cache = Arc::new(FileCache::new(cache_dir)?);
+impl Args {
+    /// Values of the flags that were passed, keyed like the config file.
+    fn config_overrides(&self) -> toml::Table {
+        let serve = match &self.command {
+            Some(Command::Serve(serve)) => serve,
+            _ => &self.serve,
+        };
 
-        Ok(Self { client, cache })
+        let mut table = toml::Table::new();
+        let mut set = |section: &str, key: &str, value: toml::Value| {
+            table
+                .entry(section.to_string())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .expect("config sections are tables")
+                .insert(key.to_string(), value);
+        };
+        let strings = |values: &[String]| {
+            toml::Value::Array(values.iter().cloned().map(toml::Value::String).collect())
+        };
+
+        if let Some(port) = serve.port {
+            set("server", "port", toml::Value::Integer(port.into()));
+        }
+        if let Some(host) = &serve.host {
+            set("server", "host", toml::Value::String(host.clone()));
+        }
+        if let Some(static_dir) = &serve.static_dir {
+            set("server", "static_dir", toml::Value::String(static_dir.clone()));
+        }
+        if let Some(cache_dir) = &self.cache_dir {
+            set("cache", "dir", toml::Value::String(cache_dir.clone()));
+        }
+        if !self.league_allow.is_empty() {
+            set("leagues", "allow", strings(&self.league_allow));
+        }
+        if !self.league_deny.is_empty() {
+            set("leagues", "deny", strings(&self.league_deny));
+        }
+        if !self.league_exclude_rules.is_empty() {
+            set("leagues", "exclude_rules", strings(&self.league_exclude_rules));
+        }
+        if self.include_event_leagues {
+            set("leagues", "include_events", toml::Value::Boolean(true));
+        }
+        if !self.private_leagues.is_empty() {
+            set("leagues", "private", strings(&self.private_leagues));
+        }
+        if let Some(log_level) = &self.log_level {
+            table.insert("log_level".to_string(), toml::Value::String(log_level.clone()));
+        }
+
+        table
     }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
+    let loaded = config::load(args.config.as_deref(), args.config_overrides())?;
+
+    let command = match args.command {
+        Some(Command::Config { action }) => return cli::config(&loaded, action),
+        command => command,
+    };
 
     // Initialize tracing
-    let log_level = match args.log_level.to_lowercase().as_str() {
+    let log_level = match loaded.config.log_level.to_lowercase().as_str() {
         "trace" => Level::TRACE,
         "debug" => Level::DEBUG,
         "info" => Level::INFO,
@@ -80,93 +172,124 @@ async fn main() -> Result<()> {
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| format!("poe_gem_calculator={}", log_level).into()),
         )
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
         .init();
 
     // Initialize application state
-    let state = AppState::new(&args.cache_dir)?;
+    let state = AppState::from_config(loaded.config)?;
 
-    // Clean up expired cache entries on startup
-    if let Err(e) = state.cache.cleanup_expired().await {
-        tracing::warn!("Failed to cleanup expired cache entries: {}", e);
+    match command {
+        None | Some(Command::Serve(_)) => serve(state).await,
+        Some(Command::Calculate(command_args)) => cli::calculate(&state, command_args).await,
+        Some(Command::Gems(command_args)) => cli::gems(&state, command_args).await,
+        Some(Command::Leagues(command_args)) => cli::leagues(&state, command_args).await,
+        Some(Command::Fetch(command_args)) => cli::fetch(&state, command_args).await,
+        Some(Command::Config { .. }) => unreachable!("handled before tracing is set up"),
     }
+}
 
-    // Build the application router
-    let app = create_router(state);
+/// Deletes expired cache entries, saved calculations and their price snapshots every
+/// `CLEANUP_INTERVAL`, starting right away, until shutdown. Reads leave expired cache
+/// entries in place so readiness can report them as stale; this keeps leagues nobody
+/// asks for any more from staying stale for good.
+async fn cleanup_expired(state: AppState) {
+    let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
-    // Create socket address
-    let addr = format!("{}:{}", args.host, args.port)
-        .parse::<SocketAddr>()?;
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if let Err(e) = state.cache.cleanup_expired().await {
+                    tracing::warn!("Failed to cleanup expired cache entries: {}", e);
+                }
+                if let Err(e) = state.results.cleanup_expired().await {
+                    tracing::warn!("Failed to cleanup expired saved calculations: {}", e);
+                }
+            }
+            _ = state.shutdown.wait() => return,
+        }
+    }
+}
 
-    info!("Server starting on http://{}", addr);
-    info!("Cache directory: {}", args.cache_dir);
+async fn serve(state: AppState) -> Result<()> {
+    // Background work that is stopped and awaited on shutdown
+    let mut background = JoinSet::new();
 
-    // Start the server
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    // Delete expired cache entries and saved calculations, on startup and then periodically
+    background.spawn(cleanup_expired(state.clone()));
 
-    Ok(())
-}
+    // Check configured private leagues against poe.ninja in the background
+    if !state.config.leagues.private.is_empty() {
+        let state = state.clone();
+        background.spawn(async move {
+            let checks = async {
+                for league in state.config.leagues.private.iter() {
+                    api::leagues::check_league_availability(&state, league).await;
+                }
+            };
+            tokio::select! {
+                _ = checks => {}
+                _ = state.shutdown.wait() => {}
+            }
+        });
+    }
 
-fn create_router(state: AppState) -> Router {
-    // API routes
-    let api_routes = Router::new()
-        .route("/leagues", get(api::get_leagues))
-        .route("/skill-gems", get(api::get_skill_gems))
-        .route("/calculate", get(api::calculate_gem_roi));
-
-    // Main application router
-    Router::new()
-        .nest("/api", api_routes)
-        .route("/health", get(health_check))
-        .fallback_service(ServeDir::new("public").append_index_html_on_directories(true))
-        .layer(
-            ServiceBuilder::new()
-                .layer(TraceLayer::new_for_http())
-                .layer(CorsLayer::permissive())
-        )
-        .with_state(state)
-}
+    // Evaluate alert rules whenever prices are refreshed
+    background.spawn(alerts::run(state.clone()));
 
-async fn health_check() -> Result<Html<&'static str>, StatusCode> {
-    Ok(Html("<html><body><h1>POE Gem Calculator - Healthy</h1></body></html>"))
-}
+    let addr = format!("{}:{}", state.config.server.host, state.config.server.port)
+        .parse::<SocketAddr>()?;
+    let cache_dir = state.config.cache.dir.clone();
+    let drain_timeout = Duration::from_secs(state.config.server.shutdown_timeout_seconds);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use axum::{
-        body::Body,
-        http::{Request, StatusCode},
-    };
-    use tower::util::ServiceExt;
+    // Build the application router
+    let app = create_router(state.clone());
 
-    #[tokio::test]
-    async fn test_health_check() {
-        let state = AppState::new("test_cache").unwrap();
-        let app = create_router(state);
+    info!("Server starting on http://{}", addr);
+    info!("Cache directory: {}", cache_dir);
 
-        let request = Request::builder()
-            .uri("/health")
-            .body(Body::empty())
-            .unwrap();
+    // Start the server; once shutdown is triggered it stops accepting connections
+    // and finishes the ones in flight
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let stopped = state.shutdown.clone();
+    let mut server = tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+            .with_graceful_shutdown(async move { stopped.wait().await })
+            .await
+    });
 
-        let response = app.oneshot(request).await.unwrap();
-        assert_eq!(response.status(), StatusCode::OK);
+    tokio::select! {
+        result = &mut server => {
+            // The server stopped on its own, which only happens on error
+            result??;
+            return Ok(());
+        }
+        _ = shutdown::signal() => state.shutdown.trigger(),
     }
 
-    #[tokio::test]
-    async fn test_api_leagues_endpoint() {
-        let state = AppState::new("test_cache").unwrap();
-        let app = create_router(state);
+    info!("Shutting down, waiting up to {}s for in-flight requests", drain_timeout.as_secs());
+    let deadline = tokio::time::Instant::now() + drain_timeout;
 
-        let request = Request::builder()
-            .uri("/api/leagues")
-            .body(Body::empty())
-            .unwrap();
+    match tokio::time::timeout_at(deadline, &mut server).await {
+        Ok(result) => result??,
+        Err(_) => {
+            tracing::warn!("Requests still running after {}s, closing them", drain_timeout.as_secs());
+            server.abort();
+        }
+    }
 
-        let response = app.oneshot(request).await.unwrap();
-        // Should either succeed or fail gracefully
-        assert!(response.status().is_success() || response.status().is_server_error());
+    if tokio::time::timeout_at(deadline, async { while background.join_next().await.is_some() {} })
+        .await
+        .is_err()
+    {
+        tracing::warn!("Background tasks did not stop in time");
+        background.abort_all();
     }
+
+    if tokio::time::timeout_at(deadline, state.cache.flush()).await.is_err() {
+        tracing::warn!("Cache writes did not finish in time");
+    }
+
+    info!("Shutdown complete");
+    Ok(())
 }
@@ -0,0 +1,206 @@
+use anyhow::Result;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use prometheus::{
+    Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use std::time::{Duration, Instant};
+use tracing::error;
+
+use crate::{cache::FileCache, engine::is_listed_variant, upstream::Upstream, AppState};
+
+/// Prometheus metrics for the HTTP server, upstream fetches, the cache and computed EVs.
+///
+/// Counters and histograms are updated as events happen; cache size and data age
+/// are read from the cache directory when `/metrics` is scraped.
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_duration: HistogramVec,
+    upstream_duration: HistogramVec,
+    upstream_errors: IntCounterVec,
+    rate_limited: IntCounterVec,
+    cache_dir_bytes: IntGauge,
+    data_age: GaugeVec,
+    expected_value: GaugeVec,
+    alert_deliveries: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new(cache: &FileCache) -> Result<Self> {
+        let registry = Registry::new_custom(Some("poe_gem".to_string()), None)?;
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route, method and status"),
+            &["route", "method", "status"],
+        )?;
+        let http_duration = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "HTTP request latency"),
+            &["route", "method", "status"],
+        )?;
+        let upstream_duration = HistogramVec::new(
+            HistogramOpts::new("upstream_request_duration_seconds", "Upstream fetch latency")
+                .buckets(vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0]),
+            &["source", "outcome"],
+        )?;
+        let upstream_errors = IntCounterVec::new(
+            Opts::new("upstream_errors_total", "Failed upstream fetches"),
+            &["source"],
+        )?;
+        let rate_limited = IntCounterVec::new(
+            Opts::new("rate_limited_total", "Requests refused by a rate limit (client or upstream budget)"),
+            &["scope"],
+        )?;
+        let cache_dir_bytes = IntGauge::new("cache_directory_bytes", "Total size of the cache directory")?;
+        let data_age = GaugeVec::new(
+            Opts::new("data_age_seconds", "Age of the cached skill gem data per league"),
+            &["league"],
+        )?;
+        let expected_value = GaugeVec::new(
+            Opts::new("expected_value_chaos", "Most recently computed transfigure EV per color"),
+            &["league", "color", "gem_level", "gem_quality"],
+        )?;
+
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_duration.clone()))?;
+        registry.register(Box::new(upstream_duration.clone()))?;
+        registry.register(Box::new(upstream_errors.clone()))?;
+        registry.register(Box::new(rate_limited.clone()))?;
+        registry.register(Box::new(cache_dir_bytes.clone()))?;
+        registry.register(Box::new(data_age.clone()))?;
+        let alert_deliveries = IntCounterVec::new(
+            Opts::new("alert_deliveries_total", "Alert webhook deliveries by result (delivered, failed)"),
+            &["result"],
+        )?;
+
+        registry.register(Box::new(expected_value.clone()))?;
+        registry.register(Box::new(alert_deliveries.clone()))?;
+        registry.register(Box::new(cache.lookup_counter().clone()))?;
+
+        Ok(Self {
+            registry,
+            http_requests,
+            http_duration,
+            upstream_duration,
+            upstream_errors,
+            rate_limited,
+            cache_dir_bytes,
+            data_age,
+            expected_value,
+            alert_deliveries,
+        })
+    }
+
+    pub fn observe_upstream(&self, upstream: Upstream, elapsed: Duration, success: bool) {
+        let outcome = if success { "success" } else { "error" };
+        self.upstream_duration
+            .with_label_values(&[upstream.as_str(), outcome])
+            .observe(elapsed.as_secs_f64());
+        if !success {
+            self.upstream_errors.with_label_values(&[upstream.as_str()]).inc();
+        }
+    }
+
+    /// Counts a request refused by the per-client (`client`) or upstream (`upstream`) limit.
+    pub fn observe_rate_limited(&self, scope: &str) {
+        self.rate_limited.with_label_values(&[scope]).inc();
+    }
+
+    /// Records a color's expected value. Only the level/quality variants poe.ninja lists
+    /// get a series, since the labels come from request parameters.
+    pub fn set_expected_value(&self, league: &str, color: &str, gem_level: u32, gem_quality: u32, value: f64) {
+        if !is_listed_variant(gem_level, gem_quality) {
+            return;
+        }
+        self.expected_value
+            .with_label_values(&[league, color, &gem_level.to_string(), &gem_quality.to_string()])
+            .set(value);
+    }
+
+    /// Counts an alert delivery once all its attempts are done.
+    pub fn observe_alert_delivery(&self, delivered: bool) {
+        let result = if delivered { "delivered" } else { "failed" };
+        self.alert_deliveries.with_label_values(&[result]).inc();
+    }
+
+    fn observe_request(&self, route: &str, method: &str, status: u16, elapsed: Duration) {
+        let status = status.to_string();
+        let labels = [route, method, status.as_str()];
+        self.http_requests.with_label_values(&labels).inc();
+        self.http_duration.with_label_values(&labels).observe(elapsed.as_secs_f64());
+    }
+
+    /// Refreshes the gauges derived from the cache directory and encodes every metric.
+    async fn render(&self, cache: &FileCache) -> Result<String> {
+        self.cache_dir_bytes.set(cache.size_bytes().await? as i64);
+
+        self.data_age.reset();
+        for entry in cache.entries().await? {
+            if let Some(league) = entry.key.strip_prefix("skillGems_") {
+                let age = (Utc::now() - entry.timestamp).num_seconds().max(0);
+                self.data_age.with_label_values(&[league]).set(age as f64);
+            }
+        }
+
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+/// Serves all metrics in the Prometheus text exposition format.
+pub async fn metrics_handler(State(state): State<AppState>) -> Response {
+    match state.metrics.render(&state.cache).await {
+        Ok(body) => ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response(),
+        Err(e) => {
+            error!("Failed to render metrics: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Middleware that counts and times every request by matched route, method and status.
+///
+/// Requests that don't match an API route (static files, 404s) share the "fallback"
+/// route label to keep cardinality bounded.
+pub async fn track_requests(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "fallback".to_string());
+    let method = request.method().to_string();
+    let started = Instant::now();
+
+    let response = next.run(request).await;
+
+    state
+        .metrics
+        .observe_request(&route, &method, response.status().as_u16(), started.elapsed());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_expected_value_only_for_listed_variants() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cache = FileCache::new(temp_dir.path()).unwrap();
+        let metrics = Metrics::new(&cache).unwrap();
+
+        metrics.set_expected_value("Standard", "red", 20, 20, 5.0);
+        metrics.set_expected_value("Standard", "red", 7, 20, 5.0);
+        metrics.set_expected_value("Standard", "red", 20, 99, 5.0);
+
+        let text = metrics.render(&cache).await.unwrap();
+        assert_eq!(text.matches("poe_gem_expected_value_chaos{").count(), 1);
+    }
+}
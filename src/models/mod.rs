@@ -1,13 +1,28 @@
 use base64::Engine;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
 pub struct League {
     pub name: String,
     #[serde(rename = "displayName")]
     pub display_name: Option<String>,
     pub hardcore: bool,
     pub indexed: bool,
+    /// Temporary challenge league, as opposed to the permanent Standard/Hardcore and to
+    /// event leagues
+    #[serde(default)]
+    pub challenge: bool,
+    /// Private league configured by the operator rather than listed by the official API
+    #[serde(default)]
+    pub private: bool,
+    /// Platform the league runs on: pc, xbox or sony
+    #[serde(default)]
+    pub realm: Option<String>,
+    #[serde(rename = "startAt", default)]
+    pub start_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(rename = "endAt", default)]
+    pub end_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 /// Response from the official PoE API for leagues
@@ -15,8 +30,11 @@ pub struct League {
 pub struct OfficialLeague {
     pub id: String,
     pub realm: Option<String>,
+    #[serde(rename = "startAt")]
+    pub start_at: Option<String>,
     #[serde(rename = "endAt")]
     pub end_at: Option<String>,
+    pub event: Option<bool>,
     pub rules: Option<Vec<LeagueRule>>,
 }
 
@@ -26,20 +44,30 @@ pub struct LeagueRule {
     pub name: Option<String>,
 }
 
+/// League index from poe.ninja, listing the leagues it has economy data for.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct LeaguesResponse {
-    #[serde(rename = "economyLeagues")]
-    pub economy_leagues: Option<Vec<League>>,
-    #[serde(rename = "oldEconomyLeagues")]
-    pub old_economy_leagues: Option<Vec<League>>,
+pub struct NinjaIndexState {
+    #[serde(rename = "economyLeagues", default)]
+    pub economy_leagues: Vec<NinjaLeague>,
+    #[serde(rename = "oldEconomyLeagues", default)]
+    pub old_economy_leagues: Vec<NinjaLeague>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+pub struct NinjaLeague {
+    pub name: String,
+    #[serde(rename = "displayName")]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub hardcore: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct LeaguesApiResponse {
     pub leagues: Vec<League>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
 pub struct SkillGem {
     pub id: Option<u32>,
     pub name: String,
@@ -53,6 +81,7 @@ pub struct SkillGem {
     #[serde(rename = "detailsId")]
     pub details_id: Option<String>,
     #[serde(rename = "tradeFilter")]
+    #[schema(value_type = Option<Object>)]
     pub trade_filter: Option<serde_json::Value>,
     pub corrupted: Option<bool>,
     #[serde(rename = "gemLevel")]
@@ -82,42 +111,46 @@ pub struct SkillGem {
     pub sparkline: Option<Sparkline>,
     #[serde(rename = "lowConfidenceSparkline")]
     pub low_confidence_sparkline: Option<Sparkline>,
-    pub implicitModifiers: Option<Vec<ImplicitModifier>>,
-    pub explicitModifiers: Option<Vec<ExplicitModifier>>,
-    pub flavourText: Option<String>,
+    #[serde(rename = "implicitModifiers")]
+    pub implicit_modifiers: Option<Vec<ImplicitModifier>>,
+    #[serde(rename = "explicitModifiers")]
+    pub explicit_modifiers: Option<Vec<ExplicitModifier>>,
+    #[serde(rename = "flavourText")]
+    pub flavour_text: Option<String>,
     #[serde(rename = "tradeInfo")]
+    #[schema(value_type = Option<Vec<Object>>)]
     pub trade_info: Option<Vec<serde_json::Value>>,
     #[serde(rename = "listingCount")]
     pub listing_count: Option<u32>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
 pub struct Sparkline {
     pub data: Option<Vec<Option<f64>>>,
     #[serde(rename = "totalChange")]
     pub total_change: Option<f64>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
 pub struct ImplicitModifier {
     pub text: String,
     pub optional: Option<bool>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
 pub struct ExplicitModifier {
     pub text: String,
     pub optional: Option<bool>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct SkillGemResponse {
     pub lines: Vec<SkillGem>,
     #[serde(rename = "currencyDetails")]
     pub currency_details: Option<Vec<CurrencyDetail>>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
 pub struct CurrencyDetail {
     pub id: Option<u32>,
     pub icon: Option<String>,
@@ -126,22 +159,66 @@ pub struct CurrencyDetail {
     pub trade_id: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct GemColorsResponse {
-    pub red: Vec<String>,
-    pub green: Vec<String>,
-    pub blue: Vec<String>,
+/// Currency overview from poe.ninja: what each currency item is worth in chaos.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CurrencyOverviewResponse {
+    pub lines: Vec<CurrencyLine>,
+    #[serde(rename = "currencyDetails")]
+    pub currency_details: Option<Vec<CurrencyDetail>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CalculationRequest {
-    pub league: Option<String>,
-    pub ignore_after_chaos: Option<f64>,
-    pub gem_level: Option<u32>,
-    pub gem_quality: Option<u32>,
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct CurrencyLine {
+    #[serde(rename = "currencyTypeName")]
+    pub currency_type_name: String,
+    #[serde(rename = "chaosEquivalent")]
+    pub chaos_equivalent: Option<f64>,
+    #[serde(rename = "detailsId")]
+    pub details_id: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Short names accepted wherever a currency is requested by name.
+const CURRENCY_ALIASES: [(&str, &str); 5] = [
+    ("chaos", "Chaos Orb"),
+    ("divine", "Divine Orb"),
+    ("exalted", "Exalted Orb"),
+    ("vaal", "Vaal Orb"),
+    ("gcp", "Gemcutter's Prism"),
+];
+
+impl CurrencyOverviewResponse {
+    /// Full name of a currency given by its short name ("divine") or full name,
+    /// ignoring case, when it is known.
+    pub fn currency_name(&self, currency: &str) -> Option<String> {
+        let name = CURRENCY_ALIASES
+            .iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(currency))
+            .map_or(currency, |(_, name)| name);
+        if name.eq_ignore_ascii_case("Chaos Orb") {
+            return Some("Chaos Orb".to_string());
+        }
+        self.lines
+            .iter()
+            .find(|line| line.currency_type_name.eq_ignore_ascii_case(name))
+            .map(|line| line.currency_type_name.clone())
+    }
+
+    /// Chaos value of one unit of a currency, by short or full name. Chaos Orbs
+    /// aren't listed and are always worth 1.
+    pub fn chaos_equivalent(&self, currency: &str) -> Option<f64> {
+        let name = self.currency_name(currency)?;
+        if name == "Chaos Orb" {
+            return Some(1.0);
+        }
+        self.lines
+            .iter()
+            .find(|line| line.currency_type_name == name)
+            .and_then(|line| line.chaos_equivalent)
+            .filter(|chaos| *chaos > 0.0)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct CalculationResponse {
     pub red_roi: f64,
     pub green_roi: f64,
@@ -149,16 +226,267 @@ pub struct CalculationResponse {
     pub red_gems: Vec<GemValue>,
     pub green_gems: Vec<GemValue>,
     pub blue_gems: Vec<GemValue>,
+    /// The expected values in the requested currency, when one was requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub converted: Option<ConvertedValues>,
+    /// Gems left out of the pools by the requested exclusions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub excluded: Vec<String>,
+    /// Gems priced with a requested override instead of poe.ninja's price
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overridden: Vec<OverriddenGem>,
+    /// Gems detected as price-fixed outliers and what the outlier policy did to them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outliers: Vec<OutlierAdjustment>,
+    /// Expected values on prices projected from each gem's 7-day trend, when requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub projected: Option<ProjectedValues>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Expected values per color on prices projected ahead from the gems' sparklines.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ProjectedValues {
+    /// Hours ahead the prices were projected
+    pub hours: f64,
+    pub red_roi: f64,
+    pub green_roi: f64,
+    pub blue_roi: f64,
+    /// Gems whose price fell sharply over the last 7 days, steepest fall first
+    pub declining: Vec<DecliningGem>,
+}
+
+/// A gem whose price fell more than `trend.decline_percent` over the last 7 days.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DecliningGem {
+    pub name: String,
+    /// red, green or blue
+    pub color: String,
+    pub chaos_value: f64,
+    pub projected_chaos_value: f64,
+    /// Price change over the last 7 days in percent
+    pub change_percent: f64,
+}
+
+/// A gem whose listing was detected as an outlier.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct OutlierAdjustment {
+    pub name: String,
+    /// Price poe.ninja lists
+    pub listed_chaos_value: f64,
+    /// Price the calculation used, or null when the gem was left out of the pool
+    pub chaos_value: Option<f64>,
+    /// Why the listing looks unreliable
+    pub reasons: Vec<String>,
+}
+
+/// A gem whose poe.ninja price was replaced for one calculation.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct OverriddenGem {
+    pub name: String,
+    /// Price the calculation used
+    pub chaos_value: f64,
+    /// Price poe.ninja lists
+    pub listed_chaos_value: f64,
+}
+
+/// Expected values per color in a currency other than chaos.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ConvertedValues {
+    /// Full name of the currency, e.g. "Divine Orb"
+    pub currency: String,
+    /// Chaos value of one unit of the currency used for the conversion
+    pub chaos_equivalent: f64,
+    pub red_roi: f64,
+    pub green_roi: f64,
+    pub blue_roi: f64,
+}
+
+impl CalculationResponse {
+    /// The expected values divided by the chaos value of one unit of `currency`.
+    pub fn in_currency(&self, currency: &str, chaos_equivalent: f64) -> ConvertedValues {
+        ConvertedValues {
+            currency: currency.to_string(),
+            chaos_equivalent,
+            red_roi: self.red_roi / chaos_equivalent,
+            green_roi: self.green_roi / chaos_equivalent,
+            blue_roi: self.blue_roi / chaos_equivalent,
+        }
+    }
+}
+
+/// A calculation pushed to stream subscribers after the league's prices were refreshed.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CalculationUpdate {
+    pub league: String,
+    /// When the price snapshot the result was computed from was fetched
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub result: CalculationResponse,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct GemValue {
     pub name: String,
     pub chaos_value: f64,
     pub probability: f64,
+    /// pathofexile.com trade search for this exact variant in the league
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trade_url: Option<String>,
+    /// poe.ninja page of this exact variant in the league
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ninja_url: Option<String>,
+    /// Price projected from the gem's 7-day trend, when a projection was requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub projected_chaos_value: Option<f64>,
+}
+
+/// Expected value of corrupting every 20/20 transfigured gem in a league with a Vaal Orb.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CorruptionResponse {
+    pub league: String,
+    /// Price of the Vaal Orb used, counted against each gem's profit
+    pub vaal_orb_chaos: f64,
+    /// Every gem with a 20/20 price, most profitable to corrupt first
+    pub gems: Vec<CorruptionValue>,
+    /// Names of the gems worth corrupting, most profitable first
+    pub corrupt: Vec<String>,
+}
+
+/// What corrupting one 20/20 transfigured gem is expected to be worth.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CorruptionValue {
+    pub name: String,
+    pub color: Option<String>,
+    /// Price of the uncorrupted 20/20 gem being corrupted
+    pub base_chaos_value: f64,
+    /// Probability-weighted price after corruption
+    pub expected_value: f64,
+    /// `expected_value` minus the gem and the Vaal Orb
+    pub profit: f64,
+    pub outcomes: Vec<CorruptionOutcomeValue>,
+}
+
+/// One possible result of the Vaal Orb and the price it is valued at.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CorruptionOutcomeValue {
+    /// no_change, level_up, level_down, quality_up, quality_down or vaal
+    pub outcome: String,
+    pub probability: f64,
+    pub chaos_value: f64,
+    /// Whether poe.ninja lists this result; unlisted results are valued like an unchanged corrupted gem
+    pub listed: bool,
+}
+
+/// Leveling and quality plans for the gems of one league, best first.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct LevelingResponse {
+    pub league: String,
+    /// Price of one Gemcutter's Prism used for the quality steps
+    pub gemcutter_chaos: f64,
+    /// Time or opportunity cost counted per gem level gained
+    pub level_cost_chaos: f64,
+    pub plans: Vec<LevelingPlan>,
+}
+
+/// Buying a gem at one level/quality, raising it and selling it at another.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct LevelingPlan {
+    pub name: String,
+    pub color: Option<String>,
+    /// level (1/0 to 20/0), quality (20/0 to 20/20) or level_and_quality (1/0 to 20/20)
+    pub step: String,
+    /// Level/quality bought, e.g. "1/0"
+    pub from: String,
+    /// Level/quality sold, e.g. "20/20"
+    pub to: String,
+    pub buy_chaos: f64,
+    pub sell_chaos: f64,
+    /// Gem levels gained
+    pub levels: u32,
+    /// Gemcutter's Prisms used, one per quality point
+    pub gemcutters: u32,
+    /// Cost of the levels and Gemcutter's Prisms
+    pub input_chaos: f64,
+    /// `sell_chaos` minus `buy_chaos` and `input_chaos`
+    pub profit: f64,
+    /// Profit per chaos spent on the gem and the inputs
+    pub roi: f64,
+    /// Listings of the gem being sold
+    pub listing_count: Option<u32>,
+}
+
+/// Everything known about a single gem in one league, across all of its variants.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct GemDetailResponse {
+    pub name: String,
+    pub league: String,
+    pub color: Option<String>,
+    pub transfigured: bool,
+    /// The gem this one is transfigured from, if it is a transfigured gem
+    pub base_gem: Option<String>,
+    /// Other transfigured gems of the same color that share the transfigure pool
+    pub color_pool: Vec<String>,
+    pub variants: Vec<GemVariant>,
 }
 
-#[derive(Debug, Clone)]
+/// One level/quality/corruption combination of a gem as listed on poe.ninja.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct GemVariant {
+    pub gem_level: u32,
+    pub gem_quality: u32,
+    pub corrupted: bool,
+    pub chaos_value: Option<f64>,
+    pub divine_value: Option<f64>,
+    pub count: Option<u32>,
+    pub listing_count: Option<u32>,
+    pub sparkline: Option<Sparkline>,
+    pub low_confidence_sparkline: Option<Sparkline>,
+}
+
+/// Liveness response: the process is up and serving requests.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct HealthResponse {
+    pub status: String,
+    pub version: String,
+    pub uptime_seconds: u64,
+}
+
+/// Readiness response explaining whether the instance can serve fresh data, and why not.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReadinessResponse {
+    /// "ready", "degraded" (serving but an upstream is failing or data is stale) or "unavailable"
+    pub status: String,
+    pub version: String,
+    pub uptime_seconds: u64,
+    pub cache: CacheStatus,
+    pub leagues: Vec<LeagueFreshness>,
+    pub upstreams: Vec<UpstreamReport>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CacheStatus {
+    pub writable: bool,
+    pub error: Option<String>,
+}
+
+/// Age of the cached skill gem data for one league.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct LeagueFreshness {
+    pub league: String,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub age_minutes: i64,
+    pub fresh: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UpstreamReport {
+    pub name: String,
+    pub healthy: bool,
+    pub last_success: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_failure: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum GemColor {
     Red,
     Green,
@@ -241,6 +569,25 @@ mod tests {
         assert!(GemColor::from_icon_url(support_url).is_none());
     }
 
+    #[test]
+    fn test_currency_chaos_equivalent() {
+        let overview: CurrencyOverviewResponse = serde_json::from_value(serde_json::json!({
+            "lines": [
+                { "currencyTypeName": "Divine Orb", "chaosEquivalent": 150.0 },
+                { "currencyTypeName": "Gemcutter's Prism", "chaosEquivalent": 0.8 },
+                { "currencyTypeName": "Mirror Shard" }
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(overview.chaos_equivalent("divine"), Some(150.0));
+        assert_eq!(overview.chaos_equivalent("gemcutter's prism"), Some(0.8));
+        assert_eq!(overview.chaos_equivalent("Chaos"), Some(1.0));
+        assert_eq!(overview.currency_name("GCP").as_deref(), Some("Gemcutter's Prism"));
+        assert_eq!(overview.chaos_equivalent("Mirror Shard"), None);
+        assert_eq!(overview.currency_name("Mirror of Kalandra"), None);
+    }
+
     #[test]
     fn test_is_transfigured_gem() {
         assert!(is_transfigured_gem("Spark of Unpredictability"));
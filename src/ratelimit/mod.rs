@@ -0,0 +1,274 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::{config::RateLimitConfig, AppState};
+
+/// Buckets kept before full ones, then the longest idle ones, are dropped.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Token bucket rate limiter with one bucket per key.
+///
+/// Each bucket holds up to `capacity` tokens and refills continuously at
+/// `per_minute` tokens per minute. A limiter created with `per_minute` 0 allows
+/// everything.
+#[derive(Debug)]
+pub struct RateLimiter<K> {
+    capacity: f64,
+    refill_per_second: f64,
+    buckets: Mutex<HashMap<K, Bucket>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl<K: Eq + Hash + Clone> RateLimiter<K> {
+    pub fn new(capacity: u32, per_minute: u32) -> Self {
+        Self {
+            capacity: f64::from(capacity.max(1)),
+            refill_per_second: f64::from(per_minute) / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn is_unlimited(&self) -> bool {
+        self.refill_per_second == 0.0
+    }
+
+    /// Takes a token for `key`, or returns how long until one is available.
+    pub fn check(&self, key: &K) -> Result<(), Duration> {
+        self.check_at(key, Instant::now())
+    }
+
+    /// How long until `key` has a token again, zero if it has one now.
+    pub fn retry_after(&self, key: &K) -> Duration {
+        if self.is_unlimited() {
+            return Duration::ZERO;
+        }
+        let now = Instant::now();
+        let buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let tokens = buckets.get(key).map_or(self.capacity, |bucket| self.refilled(bucket, now));
+        self.wait_for_token(tokens)
+    }
+
+    fn check_at(&self, key: &K, now: Instant) -> Result<(), Duration> {
+        if self.is_unlimited() {
+            return Ok(());
+        }
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(key) {
+            // Forgetting a full bucket loses nothing, it would start full again
+            buckets.retain(|_, bucket| self.refilled(bucket, now) < self.capacity);
+            // Many clients mid-burst (or one cycling through addresses) must not grow
+            // the map without bound, so the longest idle bucket makes room
+            if buckets.len() >= MAX_TRACKED_CLIENTS {
+                let idlest = buckets.iter().min_by_key(|(_, bucket)| bucket.updated).map(|(key, _)| key.clone());
+                if let Some(idlest) = idlest {
+                    buckets.remove(&idlest);
+                }
+            }
+        }
+
+        let bucket = buckets.entry(key.clone()).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(self.wait_for_token(bucket.tokens))
+        }
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.refill_per_second).min(self.capacity)
+    }
+
+    fn wait_for_token(&self, tokens: f64) -> Duration {
+        if tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - tokens) / self.refill_per_second)
+        }
+    }
+}
+
+/// Request budgets for API clients and for calls to poe.ninja and the official API.
+#[derive(Debug)]
+pub struct RateLimits {
+    /// Per client, keyed by IP address
+    pub clients: RateLimiter<String>,
+    /// Shared by every upstream request the server makes
+    pub upstream: RateLimiter<()>,
+    trust_forwarded_for: bool,
+    trusted_proxies: usize,
+}
+
+impl RateLimits {
+    pub fn from_config(config: &RateLimitConfig) -> Self {
+        Self {
+            clients: RateLimiter::new(config.burst, config.requests_per_minute),
+            upstream: RateLimiter::new(config.upstream_requests_per_minute, config.upstream_requests_per_minute),
+            trust_forwarded_for: config.trust_forwarded_for,
+            trusted_proxies: config.trusted_proxies as usize,
+        }
+    }
+
+    /// Identifies the client by the peer address, or when the server is configured to
+    /// trust `X-Forwarded-For` (behind reverse proxies), by the address the outermost
+    /// trusted proxy appended: `trusted_proxies` entries from the right. Entries further
+    /// left are whatever the client sent and can't be used as its identity.
+    fn client_key(&self, headers: &HeaderMap, peer: Option<SocketAddr>) -> String {
+        let forwarded = self
+            .trust_forwarded_for
+            .then(|| {
+                let header = headers.get("x-forwarded-for")?.to_str().ok()?;
+                header.rsplit(',').nth(self.trusted_proxies.checked_sub(1)?)
+            })
+            .flatten()
+            .map(str::trim)
+            .filter(|ip| !ip.is_empty());
+
+        match (forwarded, peer) {
+            (Some(ip), _) => ip.to_string(),
+            (None, Some(peer)) => peer.ip().to_string(),
+            (None, None) => "unknown".to_string(),
+        }
+    }
+}
+
+/// Middleware that answers 429 with `Retry-After` once a client used up its budget.
+///
+/// Handlers refused by the upstream budget also answer 429; those responses get a
+/// `Retry-After` for when the next upstream request is allowed.
+pub async fn limit_clients(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    let client = state.rate_limits.client_key(request.headers(), peer);
+
+    if let Err(retry_after) = state.rate_limits.clients.check(&client) {
+        warn!("Rate limited client {} on {}", client, request.uri().path());
+        state.metrics.observe_rate_limited("client");
+        return too_many_requests(retry_after);
+    }
+
+    let mut response = next.run(request).await;
+    if response.status() == StatusCode::TOO_MANY_REQUESTS && !response.headers().contains_key(header::RETRY_AFTER) {
+        let retry_after = state.rate_limits.upstream.retry_after(&());
+        response.headers_mut().insert(header::RETRY_AFTER, retry_after_value(retry_after));
+    }
+    response
+}
+
+fn too_many_requests(retry_after: Duration) -> Response {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, retry_after_value(retry_after))],
+        "Too many requests",
+    )
+        .into_response()
+}
+
+/// Whole seconds, rounded up so clients don't retry too early.
+fn retry_after_value(retry_after: Duration) -> HeaderValue {
+    let seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+    HeaderValue::from(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_rate_limiter_refills() {
+        let limiter = RateLimiter::new(2, 60);
+        let start = Instant::now();
+
+        assert!(limiter.check_at(&"a", start).is_ok());
+        assert!(limiter.check_at(&"a", start).is_ok());
+        let retry_after = limiter.check_at(&"a", start).unwrap_err();
+        assert!(retry_after > Duration::from_millis(900) && retry_after <= Duration::from_secs(1));
+
+        // Other clients have their own bucket
+        assert!(limiter.check_at(&"b", start).is_ok());
+
+        // One token per second comes back
+        assert!(limiter.check_at(&"a", start + Duration::from_secs(1)).is_ok());
+        assert!(limiter.check_at(&"a", start + Duration::from_secs(1)).is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter_disabled() {
+        let limiter = RateLimiter::new(1, 0);
+        for _ in 0..100 {
+            assert!(limiter.check(&()).is_ok());
+        }
+        assert_eq!(limiter.retry_after(&()), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_client_key() {
+        let mut config = Config::default().rate_limit;
+        let peer: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_static("203.0.113.7, 10.0.0.2"));
+
+        let limits = RateLimits::from_config(&config);
+        assert_eq!(limits.client_key(&headers, Some(peer)), "10.0.0.1");
+
+        // The proxy appended the address it saw, anything before it is client supplied
+        config.trust_forwarded_for = true;
+        let limits = RateLimits::from_config(&config);
+        assert_eq!(limits.client_key(&headers, Some(peer)), "10.0.0.2");
+        assert_eq!(limits.client_key(&HeaderMap::new(), None), "unknown");
+
+        config.trusted_proxies = 2;
+        let limits = RateLimits::from_config(&config);
+        assert_eq!(limits.client_key(&headers, Some(peer)), "203.0.113.7");
+
+        // Fewer entries than proxies: the header didn't come through all of them
+        config.trusted_proxies = 3;
+        let limits = RateLimits::from_config(&config);
+        assert_eq!(limits.client_key(&headers, Some(peer)), "10.0.0.1");
+    }
+
+    #[test]
+    fn test_rate_limiter_bounds_tracked_clients() {
+        let limiter = RateLimiter::new(2, 60);
+        let start = Instant::now();
+
+        // Every bucket is mid-burst, so none can be dropped for being full
+        for client in 0..MAX_TRACKED_CLIENTS {
+            let now = start + Duration::from_micros(client as u64);
+            assert!(limiter.check_at(&client, now).is_ok());
+        }
+        let now = start + Duration::from_millis(100);
+        assert!(limiter.check_at(&MAX_TRACKED_CLIENTS, now).is_ok());
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), MAX_TRACKED_CLIENTS);
+        assert!(!buckets.contains_key(&0));
+        assert!(buckets.contains_key(&1));
+    }
+}
@@ -0,0 +1,702 @@
+use anyhow::Result;
+use axum::{
+    middleware,
+    routing::{get, post, Router},
+};
+use reqwest::Client;
+use std::{path::Path, sync::Arc, time::Instant};
+use tokio::sync::broadcast;
+use tower::ServiceBuilder;
+use tower_http::{
+    compression::CompressionLayer,
+    cors::CorsLayer,
+    services::{ServeDir, ServeFile},
+    trace::TraceLayer,
+};
+use utoipa::OpenApi;
+use utoipa_rapidoc::RapiDoc;
+
+use crate::{
+    alerts::AlertStore,
+    api::{self, stream::SnapshotUpdate},
+    assets,
+    cache::FileCache,
+    config::Config,
+    filter::LeagueFilter,
+    metrics::{self, Metrics},
+    ratelimit::{self, RateLimits},
+    shutdown::Shutdown,
+    upstream::UpstreamMonitor,
+};
+
+/// Shared state of the web server and CLI commands: configuration, the HTTP client,
+/// the cache and the monitoring handles.
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<Config>,
+    pub client: Client,
+    pub cache: Arc<FileCache>,
+    /// Saved calculations, kept apart from the cache so clearing it doesn't break permalinks
+    pub results: Arc<FileCache>,
+    pub alerts: Arc<AlertStore>,
+    pub upstreams: Arc<UpstreamMonitor>,
+    pub league_filter: Arc<LeagueFilter>,
+    pub metrics: Arc<Metrics>,
+    pub rate_limits: Arc<RateLimits>,
+    pub snapshot_updates: broadcast::Sender<SnapshotUpdate>,
+    pub shutdown: Shutdown,
+    pub started_at: Instant,
+}
+
+impl AppState {
+    /// State with the default configuration and the given cache directory.
+    pub fn new(cache_dir: &str) -> Result<Self> {
+        let mut config = Config::default();
+        config.cache.dir = cache_dir.to_string();
+        Self::from_config(config)
+    }
+
+    pub fn from_config(config: Config) -> Result<Self> {
+        let client = Client::builder()
+            .user_agent(&config.http.user_agent)
+            .timeout(std::time::Duration::from_secs(config.http.timeout_seconds))
+            .build()?;
+
+        let cache = Arc::new(FileCache::new(&config.cache.dir)?);
+        let results = Arc::new(FileCache::new(Path::new(&config.cache.dir).join("results"))?);
+        let alerts = Arc::new(AlertStore::open(
+            Path::new(&config.cache.dir).join("alerts"),
+            config.alerts.delivery_log_size,
+            config.alerts.max_rules,
+        )?);
+        let metrics = Arc::new(Metrics::new(&cache)?);
+        let league_filter = LeagueFilter {
+            allow: config.leagues.allow.clone(),
+            deny: config.leagues.deny.clone(),
+            excluded_rules: config.leagues.exclude_rules.clone(),
+            include_events: config.leagues.include_events,
+        };
+
+        let rate_limits = Arc::new(RateLimits::from_config(&config.rate_limit));
+
+        Ok(Self {
+            config: Arc::new(config),
+            client,
+            cache,
+            results,
+            alerts,
+            upstreams: Arc::new(UpstreamMonitor::new()),
+            league_filter: Arc::new(league_filter),
+            metrics,
+            rate_limits,
+            snapshot_updates: broadcast::channel(64).0,
+            shutdown: Shutdown::new(),
+            started_at: Instant::now(),
+        })
+    }
+}
+
+/// Builds the web server: the API under `/api/v1` (and the legacy `/api`), its docs,
+/// health and metrics endpoints, and the static frontend.
+pub fn create_router(state: AppState) -> Router {
+    // API routes, served under the versioned prefix and the legacy unversioned one
+    let api_routes = Router::new()
+        .route("/leagues", get(api::get_leagues))
+        .route("/skill-gems", get(api::get_skill_gems))
+        .route("/gems/:name", get(api::get_gem_details))
+        .route("/calculate", get(api::calculate_gem_roi))
+        .route("/calculate/defaults", get(api::get_calculation_defaults))
+        .route("/calculate/stream", get(api::stream_calculation))
+        .route("/corruption", get(api::get_corruption_values))
+        .route("/leveling", get(api::get_leveling_plans))
+        .route("/currency", get(api::get_currency))
+        .route("/results", post(api::save_result))
+        .route("/results/:id", get(api::get_result))
+        .route("/alerts", get(api::list_alert_rules).post(api::create_alert_rule))
+        .route("/alerts/deliveries", get(api::list_alert_deliveries))
+        .route("/alerts/:id", get(api::get_alert_rule).delete(api::delete_alert_rule))
+        .route("/alerts/:id/test", post(api::test_alert_rule))
+        .layer(middleware::from_fn_with_state(state.clone(), ratelimit::limit_clients));
+
+    // Main application router
+    let router = Router::new()
+        .nest("/api/v1", api_routes.clone())
+        .nest("/api", api_routes)
+        .merge(RapiDoc::with_openapi("/api/v1/openapi.json", api::ApiDoc::openapi()).path("/api/docs"))
+        .route("/health", get(api::health_check))
+        .route("/ready", get(api::readiness_check))
+        .route("/metrics", get(metrics::metrics_handler));
+
+    // Frontend, embedded unless a directory to serve it from is configured. Permalinks
+    // of saved calculations are pages of the frontend, which loads them by id
+    let router = match state.config.server.static_dir.as_str() {
+        "" => router
+            .route("/results/:id", get(assets::serve_embedded_index))
+            .fallback(assets::serve_embedded),
+        dir => router
+            .route_service("/results/:id", ServeFile::new(Path::new(dir).join("index.html")))
+            .fallback_service(ServeDir::new(dir).append_index_html_on_directories(true)),
+    };
+
+    router
+        .layer(middleware::from_fn_with_state(state.clone(), metrics::track_requests))
+        .layer(
+            ServiceBuilder::new()
+                .layer(TraceLayer::new_for_http())
+                .layer(CompressionLayer::new())
+                .layer(CorsLayer::permissive())
+        )
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use tower::util::ServiceExt;
+
+    #[tokio::test]
+    async fn test_health_check() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = AppState::new(temp_dir.path().to_str().unwrap()).unwrap();
+        let app = create_router(state);
+
+        let request = Request::builder()
+            .uri("/health")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let health: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(health["status"], "ok");
+        assert_eq!(health["version"], env!("CARGO_PKG_VERSION"));
+    }
+
+    #[tokio::test]
+    async fn test_readiness_check() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = AppState::new(temp_dir.path().to_str().unwrap()).unwrap();
+        let snapshot = serde_json::json!({ "lines": [] });
+        state.cache.set("skillGems_Foo (PL12345)", &snapshot, 0).await.unwrap();
+        // Looking up expired data doesn't hide it from the report
+        assert!(state.cache.get::<serde_json::Value>("skillGems_Foo (PL12345)").await.unwrap().is_none());
+        let app = create_router(state);
+
+        let request = Request::builder()
+            .uri("/ready")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let readiness: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(readiness["cache"]["writable"], true);
+        assert_eq!(readiness["upstreams"].as_array().unwrap().len(), 2);
+        assert_eq!(readiness["status"], "degraded");
+        assert_eq!(readiness["leagues"][0]["league"], "Foo (PL12345)");
+        assert_eq!(readiness["leagues"][0]["fresh"], false);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = AppState::new(temp_dir.path().to_str().unwrap()).unwrap();
+        let app = create_router(state);
+
+        let request = Request::builder().uri("/health").body(Body::empty()).unwrap();
+        app.clone().oneshot(request).await.unwrap();
+
+        let request = Request::builder().uri("/metrics").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        assert!(text.contains(r#"poe_gem_http_requests_total{method="GET",route="/health",status="200"} 1"#));
+        assert!(text.contains("poe_gem_cache_directory_bytes"));
+    }
+
+    #[tokio::test]
+    async fn test_expected_value_metric_only_for_default_queries() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = AppState::new(temp_dir.path().to_str().unwrap()).unwrap();
+        let gems = serde_json::json!({ "lines": [{
+            "name": "Spark of Unpredictability",
+            "icon": "https://web.poecdn.com/gen/image/WzMwLDE0LHsiZ2QiOjE0fV0/x/Spark.png",
+            "chaosValue": 10.0,
+            "tradeFilter": {}
+        }] });
+        state.cache.set("skillGems_Standard", &gems, 60).await.unwrap();
+        let app = create_router(state);
+
+        let metrics = |app: Router| async move {
+            let request = Request::builder().uri("/metrics").body(Body::empty()).unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            String::from_utf8(body.to_vec()).unwrap()
+        };
+        let calculate = |app: Router, uri: &'static str| async move {
+            let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            assert_eq!(app.oneshot(request).await.unwrap().status(), StatusCode::OK);
+        };
+
+        calculate(app.clone(), "/api/v1/calculate?league=Standard&ignore_after_chaos=1000000000").await;
+        assert!(!metrics(app.clone()).await.contains("poe_gem_expected_value_chaos{"));
+
+        calculate(app.clone(), "/api/v1/calculate?league=Standard").await;
+        assert!(metrics(app).await.contains(r#"poe_gem_expected_value_chaos{color="blue",gem_level="1",gem_quality="0",league="Standard"}"#));
+    }
+
+    #[tokio::test]
+    async fn test_calculation_stream() {
+        use http_body_util::BodyExt;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = AppState::new(temp_dir.path().to_str().unwrap()).unwrap();
+        let snapshot = serde_json::json!({ "lines": [] });
+        let updated_at = state.cache.set("skillGems_Standard", &snapshot, 60).await.unwrap();
+        let app = create_router(state);
+
+        // A fresh subscriber gets the current result straight away
+        let request = Request::builder()
+            .uri("/api/v1/calculate/stream?league=Standard")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut body = response.into_body();
+        let frame = body.frame().await.unwrap().unwrap();
+        let text = String::from_utf8(frame.into_data().unwrap().to_vec()).unwrap();
+        assert!(text.contains("event: calculation"));
+        assert!(text.contains(&format!("id: {}", updated_at.timestamp_millis())));
+
+        // A client resuming from that snapshot gets nothing until prices change
+        let request = Request::builder()
+            .uri("/api/v1/calculate/stream?league=Standard")
+            .header("Last-Event-ID", updated_at.timestamp_millis().to_string())
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let mut body = response.into_body();
+        let next = tokio::time::timeout(std::time::Duration::from_millis(200), body.frame()).await;
+        assert!(next.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_openapi_spec() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = AppState::new(temp_dir.path().to_str().unwrap()).unwrap();
+        let app = create_router(state);
+
+        let request = Request::builder()
+            .uri("/api/v1/openapi.json")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let spec: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(spec["paths"]["/api/v1/calculate"].is_object());
+        assert!(spec["components"]["schemas"]["CalculationResponse"].is_object());
+    }
+
+    #[tokio::test]
+    async fn test_embedded_frontend() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = AppState::new(temp_dir.path().to_str().unwrap()).unwrap();
+        let app = create_router(state);
+
+        let request = Request::builder().uri("/").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/html"));
+        assert_eq!(response.headers()["cache-control"], "no-cache");
+
+        let request = Request::builder()
+            .uri("/app.js")
+            .header("Accept-Encoding", "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-encoding"], "gzip");
+        let etag = response.headers()["etag"].clone();
+
+        // A cached copy is revalidated without sending the file again
+        let request = Request::builder()
+            .uri("/app.js")
+            .header("If-None-Match", etag)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        let request = Request::builder().uri("/missing.js").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_client_rate_limit() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.cache.dir = temp_dir.path().to_str().unwrap().to_string();
+        config.rate_limit.burst = 2;
+        config.rate_limit.requests_per_minute = 1;
+        let app = create_router(AppState::from_config(config).unwrap());
+
+        let request = || Request::builder().uri("/api/v1/leagues?realm=nowhere").body(Body::empty()).unwrap();
+        assert_eq!(app.clone().oneshot(request()).await.unwrap().status(), StatusCode::BAD_REQUEST);
+        assert_eq!(app.clone().oneshot(request()).await.unwrap().status(), StatusCode::BAD_REQUEST);
+
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = response.headers()["retry-after"].to_str().unwrap().parse().unwrap();
+        assert!((1..=60).contains(&retry_after));
+
+        // Probes aren't limited
+        let request = Request::builder().uri("/health").body(Body::empty()).unwrap();
+        assert_eq!(app.oneshot(request).await.unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_calculation_defaults() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.cache.dir = temp_dir.path().to_str().unwrap().to_string();
+        config.outliers.policy = crate::engine::outliers::OutlierPolicy::Winsorize;
+        let state = AppState::from_config(config).unwrap();
+        state.cache.set("skillGems_Standard", serde_json::json!({ "lines": [] }), 60).await.unwrap();
+        let app = create_router(state);
+
+        let get = |uri: &str| Request::builder().uri(uri).body(Body::empty()).unwrap();
+
+        // What the web UI's local engine needs to match the server
+        let response = app.clone().oneshot(get("/api/v1/calculate/defaults")).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let defaults: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(defaults["outliers"]["policy"], "winsorize");
+        assert_eq!(defaults["offer_size"], 3);
+        assert_eq!(defaults["trade_url"], "https://www.pathofexile.com/trade/search");
+
+        let response = app.clone().oneshot(get("/api/v1/calculate?league=Standard&offer_size=0")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/v1/results?league=Standard&offer_size=2")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let saved: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(saved["inputs"]["offer_size"], 2);
+    }
+
+    /// Router over a temporary cache holding Standard prices: three blue gems, Spark
+    /// of Unpredictability halving over the week, and Divine and Vaal Orb prices.
+    async fn calculation_app(temp_dir: &tempfile::TempDir) -> Router {
+        let state = AppState::new(temp_dir.path().to_str().unwrap()).unwrap();
+        let gems = serde_json::json!({ "lines": [
+            {
+                "name": "Spark of Unpredictability",
+                "icon": "https://web.poecdn.com/gen/image/WzMwLDE0LHsiZ2QiOjE0fV0/x/Spark.png",
+                "chaosValue": 10.0,
+                "tradeFilter": {},
+                "sparkline": { "data": [0.0, -20.0, -40.0, -50.0], "totalChange": -50.0 }
+            },
+            {
+                "name": "Arc of Surging",
+                "icon": "https://web.poecdn.com/gen/image/WzMwLDE0LHsiZ2QiOjE0fV0/x/Arc.png",
+                "chaosValue": 6.0,
+                "tradeFilter": {}
+            },
+            {
+                "name": "Arc of Oscillating",
+                "icon": "https://web.poecdn.com/gen/image/WzMwLDE0LHsiZ2QiOjE0fV0/x/Arc.png",
+                "chaosValue": 2.0,
+                "tradeFilter": {}
+            }
+        ] });
+        state.cache.set("skillGems_Standard", &gems, 60).await.unwrap();
+        let currency = serde_json::json!({ "lines": [
+            { "currencyTypeName": "Divine Orb", "chaosEquivalent": 200.0 },
+            { "currencyTypeName": "Vaal Orb", "chaosEquivalent": 2.0 }
+        ] });
+        state.cache.set("currency_Standard", &currency, 60).await.unwrap();
+        create_router(state)
+    }
+
+    fn get(uri: &str) -> Request<Body> {
+        Request::builder().uri(uri).body(Body::empty()).unwrap()
+    }
+
+    async fn json_body(response: axum::response::Response) -> serde_json::Value {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_currency_conversion() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let app = calculation_app(&temp_dir).await;
+
+        let response = app.clone().oneshot(get("/api/v1/calculate?league=Standard&currency=divine")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        assert_eq!(body["converted"]["currency"], "Divine Orb");
+        assert_eq!(body["converted"]["chaos_equivalent"], 200.0);
+
+        let response = app.clone().oneshot(get("/api/v1/calculate?league=Standard")).await.unwrap();
+        assert!(json_body(response).await.get("converted").is_none());
+
+        let response = app.oneshot(get("/api/v1/calculate?league=Standard&currency=doubloons")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_corruption_input_prices() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let app = calculation_app(&temp_dir).await;
+
+        // Crafting inputs are priced from the same currency data
+        let response = app.clone().oneshot(get("/api/v1/corruption?league=Standard")).await.unwrap();
+        assert_eq!(json_body(response).await["vaal_orb_chaos"], 2.0);
+
+        for price in ["-1", "NaN", "inf"] {
+            let uri = format!("/api/v1/corruption?league=Standard&vaal_orb_chaos={}", price);
+            let response = app.clone().oneshot(get(&uri)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exclusions_and_price_overrides() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let app = calculation_app(&temp_dir).await;
+
+        let response = app.clone().oneshot(get("/api/v1/calculate?league=Standard&exclude=spark%20of%20*")).await.unwrap();
+        assert_eq!(json_body(response).await["excluded"], serde_json::json!(["Spark of Unpredictability"]));
+
+        let response = app.oneshot(get("/api/v1/calculate?league=Standard&prices=Spark")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_calculation_exports() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let app = calculation_app(&temp_dir).await;
+
+        // By parameter
+        let response = app.clone().oneshot(get("/api/v1/calculate?league=Standard&format=csv")).await.unwrap();
+        assert_eq!(response.headers()["content-type"], "text/csv; charset=utf-8");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let csv = String::from_utf8(body.to_vec()).unwrap();
+        assert!(csv.lines().any(|line| line.contains(",blue,") && line.contains(",Spark of Unpredictability,10.00,")));
+
+        // Or by Accept header
+        let request = Request::builder()
+            .uri("/api/v1/skill-gems?league=Standard")
+            .header("Accept", "text/markdown")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.headers()["content-type"], "text/markdown; charset=utf-8");
+        assert_eq!(response.headers()["vary"], "Accept");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8(body.to_vec()).unwrap().contains("| Spark of Unpredictability | blue | 1 | 0 |"));
+
+        let response = app.oneshot(get("/api/v1/calculate?league=Standard&format=xlsx")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_trend_projection() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let app = calculation_app(&temp_dir).await;
+
+        // Projection from the 7-day trend, next to the current expected value
+        let response = app.clone().oneshot(get("/api/v1/calculate?league=Standard&projection_hours=12")).await.unwrap();
+        let body = json_body(response).await;
+        assert_eq!(body["blue_roi"], 10.0);
+        assert_eq!(body["projected"]["hours"], 12.0);
+        let projected_roi = body["projected"]["blue_roi"].as_f64().unwrap();
+        assert!(projected_roi > 8.0 && projected_roi < 10.0);
+        assert_eq!(body["blue_gems"][0]["projected_chaos_value"].as_f64(), Some(projected_roi));
+        assert_eq!(body["projected"]["declining"][0]["name"], "Spark of Unpredictability");
+        assert_eq!(body["projected"]["declining"][0]["change_percent"], -50.0);
+
+        let response = app.oneshot(get("/api/v1/calculate?league=Standard&projection_hours=500")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_saved_results() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = AppState::new(temp_dir.path().to_str().unwrap()).unwrap();
+        let gems = serde_json::json!({ "lines": [
+            {
+                "name": "Spark of Unpredictability",
+                "icon": "https://web.poecdn.com/gen/image/WzMwLDE0LHsiZ2QiOjE0fV0/x/Spark.png",
+                "chaosValue": 10.0,
+                "tradeFilter": {}
+            }
+        ] });
+        state.cache.set("skillGems_Standard", &gems, 60).await.unwrap();
+        let app = create_router(state.clone());
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/v1/results?league=Standard&gem_level=20")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let location = response.headers()["location"].to_str().unwrap().to_string();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let saved: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let id = saved["id"].as_str().unwrap();
+        assert_eq!(location, format!("/api/v1/results/{}", id));
+        assert_eq!(saved["permalink"], format!("/results/{}", id));
+        assert_eq!(saved["inputs"]["gem_level"], 20);
+        assert_eq!(saved["inputs"]["gem_quality"], 0);
+        assert!(saved.get("snapshot").is_none());
+
+        // Calculations on the same prices share one stored snapshot
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/v1/results?league=Standard")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::CREATED);
+        let keys: Vec<String> = state.results.entries().await.unwrap().into_iter().map(|entry| entry.key).collect();
+        assert_eq!(keys.len(), 3);
+        assert_eq!(keys.iter().filter(|key| key.starts_with("snapshot_Standard_")).count(), 1);
+
+        // Later price changes don't touch the saved calculation
+        state.cache.clear().await.unwrap();
+        let request = Request::builder()
+            .uri(format!("{}?include_snapshot=true", location))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let loaded: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(loaded["result"], saved["result"]);
+        assert_eq!(loaded["snapshot"]["lines"][0]["name"], "Spark of Unpredictability");
+
+        for uri in ["/api/v1/results/not-an-id", "/api/v1/results/00000000-0000-0000-0000-000000000000"] {
+            let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::NOT_FOUND);
+        }
+
+        // The permalink itself is a page of the frontend
+        let request = Request::builder().uri(format!("/results/{}", id)).body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/html"));
+    }
+
+    #[tokio::test]
+    async fn test_alert_rules() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let app = create_router(AppState::new(temp_dir.path().to_str().unwrap()).unwrap());
+        let json = |body: axum::body::Bytes| serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+        let post = |uri: &str, body: serde_json::Value| {
+            Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("Content-Type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+
+        let rule = serde_json::json!({
+            "league": "Standard", "color": "blue", "gem_level": 20, "gem_quality": 20,
+            "comparison": "above", "threshold": 150.0
+        });
+        let response = app.clone().oneshot(post("/api/v1/alerts", rule)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let location = response.headers()["location"].to_str().unwrap().to_string();
+        let created = json(axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap());
+        assert_eq!(created["cooldown_minutes"], 60);
+
+        let invalid = serde_json::json!({ "league": "Standard", "color": "purple", "comparison": "above", "threshold": 1.0 });
+        let response = app.clone().oneshot(post("/api/v1/alerts", invalid)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let request = Request::builder().uri("/api/v1/alerts").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let rules = json(axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap());
+        assert_eq!(rules.as_array().unwrap().len(), 1);
+
+        // Without webhooks a test delivery has nowhere to go
+        let response = app.clone().oneshot(post(&format!("{}/test", location), serde_json::json!({}))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json(axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap()), serde_json::json!([]));
+
+        let request = Request::builder().uri("/api/v1/alerts/deliveries").body(Body::empty()).unwrap();
+        assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+
+        let delete = || Request::builder().method("DELETE").uri(&location).body(Body::empty()).unwrap();
+        assert_eq!(app.clone().oneshot(delete()).await.unwrap().status(), StatusCode::NO_CONTENT);
+        assert_eq!(app.clone().oneshot(delete()).await.unwrap().status(), StatusCode::NOT_FOUND);
+        let request = Request::builder().uri(&location).body(Body::empty()).unwrap();
+        assert_eq!(app.oneshot(request).await.unwrap().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_league_and_upstream_budget() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state = AppState::new(temp_dir.path().to_str().unwrap()).unwrap();
+        state.cache.set("unknownLeague_Nowhere", true, 10).await.unwrap();
+        while state.rate_limits.upstream.check(&()).is_ok() {}
+        let app = create_router(state);
+
+        // Remembered as unknown, answered without asking POE Ninja
+        let request = Request::builder().uri("/api/v1/calculate?league=Nowhere").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // Anything else would need an upstream request, and the budget is used up
+        let request = Request::builder().uri("/api/v1/calculate?league=Elsewhere").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key("retry-after"));
+    }
+
+    #[tokio::test]
+    async fn test_api_leagues_endpoint() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.cache.dir = temp_dir.path().to_str().unwrap().to_string();
+        // Nothing listens here, so the request fails without leaving the machine
+        config.upstream.poe_api_url = "http://127.0.0.1:1".to_string();
+        config.upstream.poe_ninja_url = "http://127.0.0.1:1".to_string();
+        let app = create_router(AppState::from_config(config).unwrap());
+
+        let request = Request::builder()
+            .uri("/api/leagues")
+            .body(Body::empty())
+            .unwrap();
+
+        // An unreachable API falls back to the permanent leagues
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let leagues: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(leagues["leagues"][0]["name"], "Standard");
+    }
+}
@@ -0,0 +1,90 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// External services the calculator depends on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Upstream {
+    PoeNinja,
+    PoeApi,
+}
+
+impl Upstream {
+    pub const ALL: [Upstream; 2] = [Upstream::PoeNinja, Upstream::PoeApi];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Upstream::PoeNinja => "poe.ninja",
+            Upstream::PoeApi => "api.pathofexile.com",
+        }
+    }
+}
+
+/// Last known outcome of requests to one upstream.
+#[derive(Debug, Clone, Default)]
+pub struct UpstreamStatus {
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_failure: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+impl UpstreamStatus {
+    /// An upstream is healthy unless its most recent request failed.
+    pub fn is_healthy(&self) -> bool {
+        match (self.last_success, self.last_failure) {
+            (_, None) => true,
+            (None, Some(_)) => false,
+            (Some(success), Some(failure)) => success > failure,
+        }
+    }
+}
+
+/// Records the last success and failure of each upstream so readiness checks can
+/// explain why an instance is degraded.
+#[derive(Debug, Default)]
+pub struct UpstreamMonitor {
+    statuses: RwLock<HashMap<Upstream, UpstreamStatus>>,
+}
+
+impl UpstreamMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_success(&self, upstream: Upstream) {
+        let mut statuses = self.statuses.write().unwrap_or_else(|e| e.into_inner());
+        statuses.entry(upstream).or_default().last_success = Some(Utc::now());
+    }
+
+    pub fn record_failure(&self, upstream: Upstream, error: impl ToString) {
+        let mut statuses = self.statuses.write().unwrap_or_else(|e| e.into_inner());
+        let status = statuses.entry(upstream).or_default();
+        status.last_failure = Some(Utc::now());
+        status.last_error = Some(error.to_string());
+    }
+
+    pub fn status(&self, upstream: Upstream) -> UpstreamStatus {
+        let statuses = self.statuses.read().unwrap_or_else(|e| e.into_inner());
+        statuses.get(&upstream).cloned().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upstream_health_follows_latest_outcome() {
+        let monitor = UpstreamMonitor::new();
+        assert!(monitor.status(Upstream::PoeNinja).is_healthy());
+
+        monitor.record_failure(Upstream::PoeNinja, "connection refused");
+        let status = monitor.status(Upstream::PoeNinja);
+        assert!(!status.is_healthy());
+        assert_eq!(status.last_error.as_deref(), Some("connection refused"));
+
+        monitor.record_success(Upstream::PoeNinja);
+        assert!(monitor.status(Upstream::PoeNinja).is_healthy());
+        assert!(monitor.status(Upstream::PoeApi).last_success.is_none());
+    }
+}
@@ -0,0 +1,77 @@
+//! WebAssembly bindings of the EV engine, so the web UI can download a league's gem
+//! list once and recompute results locally as the options change. The server's
+//! `/api/v1/calculate` stays the reference implementation.
+//!
+//! Built with `wasm-pack build wasm --target web --out-dir ../public/wasm`.
+
+use poe_gem_calculator::{
+    engine::{calculate_with_options, CalculationOptions},
+    links::add_links,
+    models::SkillGemResponse,
+};
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+/// A league's skill gem overview, parsed once and priced as often as needed.
+#[wasm_bindgen]
+pub struct Calculator {
+    overview: SkillGemResponse,
+    links: Option<Links>,
+}
+
+/// Where the trade and poe.ninja links of the calculated gems point.
+struct Links {
+    league: String,
+    trade_url: String,
+    poe_ninja_url: String,
+}
+
+#[wasm_bindgen]
+impl Calculator {
+    /// Parses the JSON body of `/api/v1/skill-gems`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(overview_json: &str) -> Result<Calculator, JsError> {
+        let overview = serde_json::from_str(overview_json)?;
+        Ok(Self { overview, links: None })
+    }
+
+    /// Adds `trade_url` and `ninja_url` links for `league` to every calculated gem, like
+    /// the server does, from the base URLs of `/api/v1/calculate/defaults`.
+    pub fn link_to(&mut self, league: &str, trade_url: &str, poe_ninja_url: &str) {
+        self.links = Some(Links {
+            league: league.to_string(),
+            trade_url: trade_url.to_string(),
+            poe_ninja_url: poe_ninja_url.to_string(),
+        });
+    }
+
+    /// Expected value per color, shaped like the `/api/v1/calculate` response.
+    ///
+    /// `options` is an object with any of `gem_level`, `gem_quality`,
+    /// `ignore_after_chaos`, `offer_size`, `excluded` (gem names or `*` patterns) and
+    /// `price_overrides` (chaos by gem name or pattern), `outliers` (`policy` and
+    /// thresholds) and `trend` (`hours`, `max_change_percent` and `decline_percent`, to
+    /// also project prices from their 7-day trend); missing fields use the engine defaults,
+    /// so pass the server's from `/api/v1/calculate/defaults` to get its results.
+    pub fn calculate(&self, options: JsValue) -> Result<JsValue, JsError> {
+        let options: CalculationOptions = if options.is_undefined() || options.is_null() {
+            CalculationOptions::default()
+        } else {
+            serde_wasm_bindgen::from_value(options)?
+        };
+
+        let mut result = calculate_with_options(&self.overview, &options);
+        if let Some(links) = &self.links {
+            add_links(
+                &mut result,
+                &self.overview,
+                &links.league,
+                options.gem_level,
+                options.gem_quality,
+                &links.trade_url,
+                &links.poe_ninja_url,
+            );
+        }
+        Ok(result.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+    }
+}